@@ -1,7 +1,10 @@
 //! Implementation of the Amplifier Circuit described in day 7 of the 2019 [Advent of Code](adventofcode.com)
 
-use intcode_channel_io::IntcodeThread;
-use intcode_computer::{read_program_from_file, Opcode};
+use intcode_channel_io::{
+    IntcodeThread, MachineId, MachineState, RecvError, Scheduler, SchedulerStatus,
+};
+use intcode_computer::{read_program_from_file, IntcodeError, Limits, Opcode, ProgramMemory};
+use std::time::{Duration, Instant};
 
 /// An amplifier, that is initially set to a phase mode
 /// and then able to receive a signal and amplify (send) it.
@@ -17,7 +20,7 @@ impl Amplifier {
         let program = read_program_from_file(&program_file);
         let identifier = Some(format!("Amp {}", phase_setting));
 
-        let thread = IntcodeThread::new(program, identifier);
+        let thread = IntcodeThread::with_limits(program, identifier, amplifier_limits());
 
         let amp = Amplifier {
             phase_setting,
@@ -40,7 +43,132 @@ impl Amplifier {
         self.thread.recv()
     }
 
+    /// Like recv, but gives up after timeout and tells why nothing was received.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Opcode, RecvError> {
+        self.thread.recv_timeout(timeout)
+    }
+
     pub fn has_exited(&self) -> bool {
         self.thread.has_exited()
     }
+
+    /// The error that stopped the program, for example because it exceeded its limits.
+    pub fn error(&self) -> Option<IntcodeError> {
+        self.thread.error()
+    }
+}
+
+/// Number of instructions an amplifier may execute, the programs of day 7 need a few hundred.
+const MAX_INSTRUCTIONS: u64 = 1_000_000;
+
+/// Time a feedback loop may take, before its amplifiers are stopped.
+const FEEDBACK_LOOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Limits for every amplifier, so that a program looping forever does not stop the whole search.
+fn amplifier_limits() -> Limits {
+    Limits {
+        max_instructions: Some(MAX_INSTRUCTIONS),
+        ..Limits::none()
+    }
+}
+
+/// The state of every amplifier, for example `Amp 5: Blocked, Amp 6: Halted`.
+fn states(scheduler: &Scheduler, amplifiers: &[MachineId]) -> String {
+    let states: Vec<String> = amplifiers
+        .iter()
+        .map(|&id| format!("{}: {:?}", scheduler.identifier(id), scheduler.state(id)))
+        .collect();
+    states.join(", ")
+}
+
+/// The first of the amplifiers, that failed, together with its error.
+fn failure(scheduler: &Scheduler, amplifiers: &[MachineId]) -> Option<String> {
+    amplifiers.iter().find_map(|&id| match scheduler.state(id) {
+        MachineState::Failed(err) => Some(format!("{} failed: {}", scheduler.identifier(id), err)),
+        _ => None,
+    })
+}
+
+/// Runs the amplifiers with the given phase settings in a feedback loop on the current thread
+/// and returns the last signal of the last amplifier.
+/// Fails if one of the amplifiers fails, for example because it exceeds its limits or the loop takes too long,
+/// and if the amplifiers wait for input, while the last one did not output anything.
+pub fn feedback_loop(program: &ProgramMemory, setting: &[u8]) -> Result<Opcode, String> {
+    let limits = Limits {
+        deadline: Some(Instant::now() + FEEDBACK_LOOP_TIMEOUT),
+        ..amplifier_limits()
+    };
+    let mut scheduler = Scheduler::new();
+    let amplifiers: Vec<MachineId> = setting
+        .iter()
+        .map(|&phase| {
+            let identifier = Some(format!("Amp {}", phase));
+            let id = scheduler.add_with_limits(program.clone(), identifier, limits);
+            scheduler.send(id, phase.into());
+            id
+        })
+        .collect();
+    for pair in amplifiers.windows(2) {
+        scheduler.connect(pair[0], pair[1]);
+    }
+
+    // the output of the last amplifier is fed back to the first one, until they halt
+    let (first, last) = (amplifiers[0], amplifiers[amplifiers.len() - 1]);
+    scheduler.send(first, 0);
+    loop {
+        let status = scheduler.run_until_idle();
+        if let Some(failure) = failure(&scheduler, &amplifiers) {
+            return Err(failure);
+        }
+        match (status, scheduler.recv(last)) {
+            (SchedulerStatus::Finished, Some(signal)) => return Ok(signal),
+            (SchedulerStatus::Idle, Some(signal)) => scheduler.send(first, signal),
+            (_, None) => {
+                return Err(format!(
+                    "the last amplifier did not output anything ({})",
+                    states(&scheduler, &amplifiers)
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(values: &[Opcode]) -> ProgramMemory {
+        values.iter().cloned().collect()
+    }
+
+    #[test]
+    fn test_feedback_loop() {
+        // Second example of day 7
+        let amplifier = program(&[
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ]);
+        assert_eq!(feedback_loop(&amplifier, &[9, 8, 7, 6, 5]), Ok(139629729));
+    }
+
+    #[test]
+    fn test_feedback_loop_fails() {
+        // Reads the phase setting and loops forever afterwards.
+        let endless = program(&[3, 0, 1105, 1, 2]);
+        let err = feedback_loop(&endless, &[5, 6, 7]).unwrap_err();
+        assert!(err.starts_with("Amp 5 failed: "), "{}", err);
+        assert!(err.contains(&MAX_INSTRUCTIONS.to_string()), "{}", err);
+    }
+
+    #[test]
+    fn test_feedback_loop_blocked() {
+        // Reads forever, without ever writing anything.
+        let reader = program(&[3, 5, 1105, 1, 0, 0]);
+        assert_eq!(
+            feedback_loop(&reader, &[5, 6]),
+            Err(String::from(
+                "the last amplifier did not output anything (Amp 5: Blocked, Amp 6: Blocked)"
+            ))
+        );
+    }
 }
@@ -2,8 +2,10 @@
 
 extern crate itertools;
 
-use amplification_circuit::Amplifier;
+use amplification_circuit::{feedback_loop, Amplifier};
+use intcode_computer::read_program_from_file;
 use itertools::Itertools;
+use std::time::Duration;
 
 // https://docs.rs/itertools/0.8.2/itertools/trait.Itertools.html#method.permutations
 
@@ -15,7 +17,12 @@ fn first_part(filename: &str) {
         for phase in setting {
             let amp = Amplifier::new(&filename, phase);
             amp.send(signal.clone());
-            signal = amp.recv().unwrap();
+            signal = amp
+                .recv_timeout(Duration::from_secs(10))
+                .unwrap_or_else(|err| match amp.error() {
+                    Some(error) => panic!("[Amp {}]: {}: {}", phase, err, error),
+                    None => panic!("[Amp {}]: {}", phase, err),
+                });
             // amp.handle.join().unwrap();
         }
         if signal > max_signal {
@@ -27,21 +34,11 @@ fn first_part(filename: &str) {
 
 #[allow(dead_code)]
 fn second_part(filename: &str) {
+    let program = read_program_from_file(filename);
     let mut max_signal = 0;
     for setting in (5..10).permutations(5) {
-        let amplifiers: Vec<Amplifier> = setting
-            .iter()
-            .map(|i| Amplifier::new(&filename, *i))
-            .collect();
-        let mut signal = 0;
-        while !amplifiers.last().unwrap().has_exited() {
-            for i in 0..5 {
-                amplifiers[i].send(signal.clone());
-                // this will return Option::None if the thread has halted
-                // in this case, we will go this loop one final time and the outer loop condition will fail afterwards
-                signal = amplifiers[i].recv().unwrap_or(signal);
-            }
-        }
+        let signal = feedback_loop(&program, &setting)
+            .unwrap_or_else(|err| panic!("phase setting {:?}: {}", setting, err));
 
         println!("signal = {}", signal);
 
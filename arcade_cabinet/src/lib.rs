@@ -1,7 +1,8 @@
 //! Interface for the Arcade Cabinet Game from day 13 of [Advent of Code 2019](adventofcode.com)
 
-use intcode_computer::{run_program, InfiniteVector, IntcodeIo, Opcode, ProgramMemory};
-use std::cell::RefCell;
+use intcode_computer::{
+    run_program, run_program_traced, InfiniteVector, IntcodeIoMut, Opcode, ProgramMemory, Tracer,
+};
 use std::convert::TryInto;
 use std::io;
 use std::ops::{Index, IndexMut};
@@ -126,38 +127,32 @@ struct ArcadeCabinetOutputBuffer {
 
 /// Maps from intcode computer Opcode IO to a screen state and handles stdin and stdout for the player.
 struct ArcadeCabinetIo {
-    pub screen: RefCell<TileScreen>,
-    pub score: RefCell<Opcode>,
-    nmoves: RefCell<usize>,
+    pub screen: TileScreen,
+    pub score: Opcode,
+    nmoves: usize,
     stdin: io::Stdin,
-    buffered_output: RefCell<ArcadeCabinetOutputBuffer>,
+    buffered_output: ArcadeCabinetOutputBuffer,
     automatic_mode: bool,
 }
 
 impl ArcadeCabinetIo {
     pub fn new(automatic_mode: bool) -> Self {
-        let stdin = io::stdin();
-        let screen = RefCell::new(TileScreen::new());
-        let nmoves = RefCell::new(0);
-        let score = RefCell::new(0);
-        let buffered_output = RefCell::new(ArcadeCabinetOutputBuffer {
-            buffer: [None, None, None],
-            buffered_opcodes: 0,
-        });
-
         Self {
-            stdin,
-            screen,
-            nmoves,
-            score,
-            buffered_output,
+            stdin: io::stdin(),
+            screen: TileScreen::new(),
+            nmoves: 0,
+            score: 0,
+            buffered_output: ArcadeCabinetOutputBuffer {
+                buffer: [None, None, None],
+                buffered_opcodes: 0,
+            },
             automatic_mode,
         }
     }
 
     /// Print the current screen state to stdout.
     pub fn print_screen(&self) {
-        let screen = self.screen.borrow_mut();
+        let screen = &self.screen;
         for y in 0..screen.len() {
             let row = &screen[y];
             for x in 0..row.len() {
@@ -173,13 +168,13 @@ impl ArcadeCabinetIo {
 
     /// Prints the current score.
     pub fn print_score(&self) {
-        println!("Score: {}", self.score.borrow());
+        println!("Score: {}", self.score);
     }
 
     /// Consume all three buffered Opcodes and update state accordingly.
     /// This will either update the screen or the score.
-    fn handle_buffer(&self) {
-        let mut buffered_output = self.buffered_output.borrow_mut();
+    fn handle_buffer(&mut self) {
+        let buffered_output = &mut self.buffered_output;
         assert_eq!(buffered_output.buffered_opcodes, 3);
         buffered_output.buffered_opcodes = 0;
 
@@ -189,53 +184,56 @@ impl ArcadeCabinetIo {
 
         if x == -1 && y == 0 {
             // Special output coordinates, signaling the third opcode is the current score.
-            let score = buffered_output.buffer[2].take().unwrap();
-            *self.score.borrow_mut() = score;
+            self.score = buffered_output.buffer[2].take().unwrap();
         } else {
             // Otherwise, the third opcode maps to the tile at these coordinates.
             let tile: Tile = buffered_output.buffer[2].take().unwrap().into();
             let x: Axis = x.try_into().unwrap();
             let y: Axis = y.try_into().unwrap();
             let coord = Coordinate(x, y);
-            self.screen.borrow_mut()[coord] = tile;
+            self.screen[coord] = tile;
         }
     }
 
     pub fn moves(&self) -> usize {
-        self.nmoves.borrow().clone()
+        self.nmoves
     }
 }
 
-impl IntcodeIo for ArcadeCabinetIo {
-    fn read(&self) -> Opcode {
+impl IntcodeIoMut for ArcadeCabinetIo {
+    fn input(&mut self) -> Result<Opcode, String> {
         // The intcode program wants user input, the user should now get to see the current screen.
         self.print_screen();
         self.print_score();
 
-        *self.nmoves.borrow_mut() += 1;
+        self.nmoves += 1;
 
         if self.automatic_mode {
-            let screen = self.screen.borrow();
-            let x_ball = screen
+            let x_ball = self
+                .screen
                 .iter()
                 .find_map(|row| row.iter().position(|&t| t == Tile::Ball))
-                .unwrap();
-            let x_player = screen
+                .ok_or_else(|| String::from("there is no ball to follow"))?;
+            let x_player = self
+                .screen
                 .iter()
                 .find_map(|row| row.iter().position(|&t| t == Tile::HorizontalPaddle))
-                .unwrap();
-            (x_ball as Opcode - x_player as Opcode).signum().into()
+                .ok_or_else(|| String::from("there is no paddle to move"))?;
+            Ok((x_ball as Opcode - x_player as Opcode).signum())
         } else {
             let mut val = String::new();
-            self.stdin.read_line(&mut val).unwrap();
+            self.stdin
+                .read_line(&mut val)
+                .map_err(|err| err.to_string())?;
 
             // Instead of typing -1, 0 or 1 manually, the keys a, s and d can be used instead.
-            match val.chars().next().unwrap() {
-                'a' => -1,
-                's' => 0,
-                'd' => 1,
+            match val.chars().next() {
+                Some('a') => Ok(-1),
+                Some('s') => Ok(0),
+                Some('d') => Ok(1),
                 // Matches everything else
-                unknown_char => panic!("Invalid input: {}", unknown_char),
+                Some(unknown_char) => Err(format!("Invalid input: {}", unknown_char)),
+                None => Err(String::from("stdin is closed")),
             }
         }
     }
@@ -243,22 +241,16 @@ impl IntcodeIo for ArcadeCabinetIo {
     /// The intcode program outputs something.
     /// Since the Arcade Cabinet Games always output three values that belong together,
     /// we will buffer these and only do something if we collected three opcodes.
-    fn write(&self, value: &Opcode) {
-        {
-            // This extra scope is needed, so that the mutable reference to the buffer
-            // is out of scope again when handling the buffer.
-            // If we wouldn't do so, both this method and self.handle_buffer would own a
-            // mutable reference to the buffer, which is not allowed.
-            let mut buffered_output = self.buffered_output.borrow_mut();
-            let index: usize = buffered_output.buffered_opcodes.into();
-            buffered_output.buffer[index] = Some(*value);
-            buffered_output.buffered_opcodes += 1;
-            if buffered_output.buffered_opcodes < 3 {
-                // If we do not have three values yet, we should not call self.handle_buffer, or it will panic.
-                return;
-            }
+    fn output(&mut self, value: Opcode) -> Result<(), String> {
+        let buffered_output = &mut self.buffered_output;
+        let index: usize = buffered_output.buffered_opcodes.into();
+        buffered_output.buffer[index] = Some(value);
+        buffered_output.buffered_opcodes += 1;
+        // If we do not have three values yet, we should not call self.handle_buffer, or it will panic.
+        if buffered_output.buffered_opcodes == 3 {
+            self.handle_buffer();
         }
-        self.handle_buffer();
+        Ok(())
     }
 }
 
@@ -276,9 +268,23 @@ impl ArcadeCabinet {
 
     /// Run a game with <quarters> many quarters inserted into the machine.
     /// If quarters is 0, the game will not start.
-    pub fn run(&self, mut program: ProgramMemory, quarters: Opcode) {
+    pub fn run(&mut self, mut program: ProgramMemory, quarters: Opcode) {
+        program[0] = quarters;
+        run_program(&mut program, &mut self.inout);
+        self.inout.print_screen();
+        self.inout.print_score();
+    }
+
+    /// Run a game like run and pass a record of every executed instruction to the tracer,
+    /// for example a Profiler to see which parts of the game logic the automatic mode exercises.
+    pub fn run_traced(
+        &mut self,
+        mut program: ProgramMemory,
+        quarters: Opcode,
+        tracer: &mut dyn Tracer,
+    ) {
         program[0] = quarters;
-        run_program(&mut program, &self.inout);
+        run_program_traced(&mut program, &mut self.inout, tracer);
         self.inout.print_screen();
         self.inout.print_score();
     }
@@ -287,7 +293,6 @@ impl ArcadeCabinet {
     pub fn count_tile(&self, tile: Tile) -> usize {
         self.inout
             .screen
-            .borrow()
             .iter()
             .map(|v| v.iter().filter(|&&t| t == tile).count())
             .sum()
@@ -297,3 +302,49 @@ impl ArcadeCabinet {
         self.inout.moves()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use intcode_computer::assemble;
+
+    /// Draws a wall, two blocks, a paddle and a ball and sets the score.
+    /// Like the real game, the first opcode is overwritten with the number of quarters.
+    const DRAWING_GAME: &str = "
+quarters: ADD [scratch], [scratch] -> [scratch]
+          OUT #0
+          OUT #0
+          OUT #1
+          OUT #1
+          OUT #0
+          OUT #2
+          OUT #2
+          OUT #0
+          OUT #2
+          OUT #1
+          OUT #1
+          OUT #3
+          OUT #2
+          OUT #1
+          OUT #4
+          OUT #-1
+          OUT #0
+          OUT #1234
+          HLT
+scratch:  DATA 0
+";
+
+    #[test]
+    fn test_count_tiles() {
+        let program = assemble(DRAWING_GAME).unwrap();
+        let mut cabinet = ArcadeCabinet::new(true);
+        cabinet.run(program, 1);
+
+        assert_eq!(cabinet.count_tile(Tile::Wall), 1);
+        assert_eq!(cabinet.count_tile(Tile::Block), 2);
+        assert_eq!(cabinet.count_tile(Tile::HorizontalPaddle), 1);
+        assert_eq!(cabinet.count_tile(Tile::Ball), 1);
+        assert_eq!(cabinet.inout.score, 1234);
+        assert_eq!(cabinet.moves(), 0);
+    }
+}
@@ -1,14 +1,35 @@
 use arcade_cabinet::{ArcadeCabinet, Tile};
-use intcode_computer::read_program_from_file;
+use intcode_computer::{read_program_from_file, Opcode, Profiler};
+use std::fs;
+
+/// Playing for free needs two quarters.
+const QUARTERS: Opcode = 2;
 
 fn main() {
     let mut args = std::env::args();
     args.next();
-    let filename = args.next().unwrap_or(String::from("input.txt"));
+    let mut filename = args.next().unwrap_or(String::from("input.txt"));
+
+    // With `--profile <report>`, the game is run with a Profiler and its report is written to the given file.
+    let mut report_filename = None;
+    if filename == "--profile" {
+        report_filename = args.next();
+        filename = args.next().unwrap_or(String::from("input.txt"));
+    }
 
     let program = read_program_from_file(&filename);
-    let cabinet = ArcadeCabinet::new(true);
-    cabinet.run(program, 2);
+    let mut cabinet = ArcadeCabinet::new(true);
+    match report_filename {
+        Some(report_filename) => {
+            // the report shows the program as it was run, with the quarters inserted
+            let mut played = program;
+            played[0] = QUARTERS;
+            let mut profiler = Profiler::new();
+            cabinet.run_traced(played.clone(), QUARTERS, &mut profiler);
+            fs::write(&report_filename, profiler.full_report(&played)).unwrap();
+        }
+        None => cabinet.run(program, QUARTERS),
+    }
     println!("Block tiles: {}", cabinet.count_tile(Tile::Block));
     println!("Moves: {}", cabinet.moves());
 }
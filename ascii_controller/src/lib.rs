@@ -1,5 +1,4 @@
-use intcode_channel_io::IntcodeThread;
-use intcode_computer::{Opcode, ProgramMemory};
+use intcode_computer::{AsciiEvent, AsciiIo, Opcode, ProgramMemory};
 use std::collections::HashSet;
 use std::convert::TryInto;
 
@@ -9,7 +8,6 @@ use types::{AsciiMainRoutine, Coordinate, Direction, Field, Move};
 #[derive(Default)]
 pub struct AsciiController {
     map: Vec<Vec<Field>>,
-    thread: Option<IntcodeThread>,
     program: ProgramMemory,
 }
 
@@ -17,41 +15,23 @@ impl AsciiController {
     pub fn new(program: ProgramMemory) -> Self {
         let map = Vec::new();
 
-        AsciiController {
-            map,
-            thread: None,
-            program,
-        }
+        AsciiController { map, program }
     }
 
-    fn build_map(&mut self) {
-        let thread = self
-            .thread
-            .as_ref()
-            .expect("Could not build map: No intcode program thread found / self.thread == None");
-
-        // Insert the first line manually, since the protocol does not start with a newline
-        self.map.push(Vec::new());
-
-        // Receive data from the camera until the program terminates.
-        while let Some(opcode) = thread.recv() {
-            if opcode == 0xa {
-                // Start on a new line (0xa == 10 == '\n' == new line)
-                self.map.push(Vec::new());
-            } else {
-                let field = opcode
-                    .try_into()
-                    .expect("Ascii sent some non-ascii stuff...");
-                self.map.last_mut().unwrap().push(field);
-            }
-        }
+    fn build_map(&mut self, mut io: AsciiIo) {
+        // Receive lines from the camera until the program terminates.
+        let (lines, event) = io.read_lines().expect("The camera program failed");
+        assert_eq!(event, AsciiEvent::Halted, "The camera program did not halt");
+
+        self.map = lines
+            .iter()
+            .map(|line| line.chars().map(Field::from).collect())
+            .collect();
 
         // Remove empty trailing lines (remove all trailing ones, although it should be one max, right?)
-        while self.map.last().unwrap().len() == 0 {
+        while self.map.last().is_some_and(|row| row.is_empty()) {
             self.map.pop();
         }
-
-        self.thread.take().unwrap().join();
     }
 
     fn neighbours(&self, pos: Coordinate) -> Vec<Coordinate> {
@@ -112,12 +92,7 @@ impl AsciiController {
 
     /// Efectively the solution to part one of day 17, but also needed for part two.
     pub fn run_cameras(&mut self) {
-        let identifier = String::from("ASCII Camera");
-        let mut thread = IntcodeThread::new(self.program.clone(), Some(identifier));
-        thread.hide_debug_messages = true;
-
-        self.thread = Some(thread);
-        self.build_map();
+        self.build_map(AsciiIo::new(self.program.clone()));
         self.print_map();
 
         println!(
@@ -225,16 +200,10 @@ impl AsciiController {
     }
 
     pub fn walk_scaffolding(&mut self) -> Opcode {
-        // Since self.build_map resets the self.thread field and we do want to overwrite
-        // some other thread handle, we just assert.
-        assert!(self.thread.is_none());
-
-        // Spawn the intcode program thread
         // Changing address 0 from 1 to 2 starts the robot program instead of the camera program
         let mut program = self.program.clone();
         program[0] = 2;
-        let identifier = String::from("ASCII Robot");
-        let thread = IntcodeThread::new(program, Some(identifier));
+        let mut io = AsciiIo::new(program);
 
         // Find subprograms A,B,C
         let moves = self.moves_needed();
@@ -242,36 +211,32 @@ impl AsciiController {
         let routine = AsciiMainRoutine::construct_from_moves(moves, 20)
             .expect("There is no possible solution to the given scaffolding map.");
 
-        const NEWLINE: Opcode = 0x0a;
-        // Submit the main movement routine
-        {
-            let opcodes = routine.to_opcode_string();
-            // Check length at most 20 (without newline) and newline at the end
-            assert!(opcodes.len() > 0);
-            assert!(opcodes.len() <= 21);
-            assert!(*opcodes.last().unwrap() == NEWLINE);
-            for &opcode in opcodes.iter() {
-                thread.send(opcode);
-            }
+        // Submit the main movement routine and the three movement functions
+        let lines = Some(routine.to_ascii_string()).into_iter().chain(
+            routine
+                .movement_functions
+                .iter()
+                .map(|function| function.to_ascii_string()),
+        );
+        for line in lines {
+            // Check length at most 20 (without newline)
+            assert!(!line.is_empty());
+            assert!(line.len() <= 20);
+            io.write_line(&line).unwrap();
         }
-
-        // Submit the three movement functions
-        for movement_function in routine.movement_functions.iter() {
-            let opcodes = movement_function.to_opcode_string();
-            // Check length at most 20 (without newline) and newline at the end
-            assert!(opcodes.len() > 0);
-            assert!(opcodes.len() <= 21);
-            assert!(*opcodes.last().unwrap() == NEWLINE);
-            for &opcode in opcodes.iter() {
-                thread.send(opcode);
+        // No continuous video feed
+        io.write_line("n").unwrap();
+
+        // The robot prints prompts and the map, the amount of dust is the only non-ASCII output.
+        loop {
+            match io.read_line().expect("Ascii vacuum robot failed") {
+                AsciiEvent::NonAscii(collected_dust) => return collected_dust,
+                AsciiEvent::Line(_) => (),
+                AsciiEvent::NeedsInput | AsciiEvent::Halted => {
+                    panic!("Ascii vacuum robot did not send the amount of collected dust.")
+                }
             }
         }
-
-        let collected_dust = thread
-            .recv()
-            .expect("Ascii vacuum robot did not send the amount of collected dust.");
-
-        collected_dust
     }
 }
 
@@ -7,45 +7,48 @@ pub struct AsciiMovementFunction {
     pub moves: Vec<Move>,
 }
 
+/// Longest run of forward moves written as a single number, longer runs are split up: 12 moves become `9,3`.
+/// Routine search counts characters with this encoding, so changing it changes which routines fit.
+const MAX_FORWARD_RUN: usize = 9;
+
+/// Appends a run of forward moves to the parts of a movement function.
+fn push_forward(parts: &mut Vec<String>, mut forward: usize) {
+    while forward > 0 {
+        let run = forward.min(MAX_FORWARD_RUN);
+        parts.push(run.to_string());
+        forward -= run;
+    }
+}
+
 impl AsciiMovementFunction {
-    pub fn to_opcode_string(&self) -> Vec<Opcode> {
-        let mut opcodes = Vec::new();
-        let mut inc_last = false;
-        const LEFT: Opcode = 0x4c;
-        const RIGHT: Opcode = 0x52;
-        const COMMA: Opcode = 0x2c;
-        const ZERO: Opcode = 0x30;
+    /// The function as the robot expects it, like `L,R,9,3,L,L,1`.
+    pub fn to_ascii_string(&self) -> String {
+        let mut parts: Vec<String> = Vec::new();
+        // number of forward moves since the last turn
+        let mut forward = 0;
 
         for m in self.moves.iter() {
-            match &m {
-                Move::TurnLeft => opcodes.push(LEFT),
-                Move::TurnRight => opcodes.push(RIGHT),
-                Move::TurnAround => opcodes.extend([LEFT, COMMA, LEFT].iter()),
-                Move::Forward => {
-                    if inc_last {
-                        // index of the last real value (past the comma)
-                        let idx = opcodes.len() - 2;
-                        opcodes[idx] += 1;
-                        inc_last = opcodes[idx] < ZERO + 9;
-                    } else {
-                        opcodes.push(ZERO + 1);
-                        opcodes.push(COMMA);
-                        inc_last = true;
-                    }
-                    // skip pushing the comma and resetting inc_last
-                    continue;
-                }
+            if *m == Move::Forward {
+                forward += 1;
+                continue;
             }
 
-            // This is only executed for turning moves
-            opcodes.push(COMMA);
-            inc_last = false;
+            push_forward(&mut parts, forward);
+            forward = 0;
+            match m {
+                Move::TurnLeft => parts.push(String::from("L")),
+                Move::TurnRight => parts.push(String::from("R")),
+                Move::TurnAround => parts.extend(vec![String::from("L"), String::from("L")]),
+                Move::Forward => unreachable!(),
+            }
         }
+        push_forward(&mut parts, forward);
 
-        // Remove that last comma
-        opcodes.pop();
+        parts.join(",")
+    }
 
-        opcodes
+    pub fn to_opcode_string(&self) -> Vec<Opcode> {
+        self.to_ascii_string().bytes().map(Opcode::from).collect()
     }
 }
 
@@ -114,6 +117,13 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_long_runs() {
+        let f = AsciiMovementFunction::from("R,12,L,9,R,18");
+        assert_eq!(f.to_ascii_string(), "R,9,3,L,9,R,9,9");
+        assert_eq!(f.to_opcode_string().len(), 15);
+    }
+
     #[test]
     fn test_from_str() {
         use crate::types::Move::*;
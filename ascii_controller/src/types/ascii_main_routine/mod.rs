@@ -1,5 +1,3 @@
-use intcode_computer::Opcode;
-
 use super::Move;
 
 mod ascii_movement_function;
@@ -215,16 +213,14 @@ impl AsciiMainRoutine {
         Err(())
     }
 
-    pub fn to_opcode_string(&self) -> Vec<Opcode> {
+    /// The main routine as the robot expects it, like `A,B,A,C`.
+    pub fn to_ascii_string(&self) -> String {
         const FUNCTION_LETTERS: [&'static str; 3] = ["A", "B", "C"];
         self.routine
             .iter()
             .map(|&idx| FUNCTION_LETTERS[idx as usize])
             .collect::<Vec<&'static str>>()
             .join(",")
-            .bytes()
-            .map(|c| Opcode::from(c))
-            .collect()
     }
 }
 
@@ -232,6 +228,7 @@ impl AsciiMainRoutine {
 mod tests {
     use super::*;
     use crate::tests::get_map;
+    use intcode_computer::Opcode;
 
     #[test]
     fn test_find_c() {
@@ -256,6 +253,29 @@ mod tests {
             .expect("No function c found, although it exists");
     }
 
+    #[test]
+    fn test_max_opcodes() {
+        // "R,12,L,12,R,12,L,12" would fit, but long runs are split: "R,9,3,L,9,3,R,9,3,L,9,3"
+        let too_long = AsciiMovementFunction::from("R,12,L,12,R,12,L,12");
+        let fitting = AsciiMovementFunction::from("R,12,L,12");
+        assert_eq!(too_long.to_opcode_string().len(), 23);
+        assert_eq!(fitting.to_opcode_string().len(), 11);
+
+        let main = AsciiMainRoutine {
+            max_opcodes: 20,
+            needed_moves: too_long.moves.clone(),
+            ..Default::default()
+        };
+        let mut c_func = AsciiMovementFunction::default();
+        assert!(main
+            .next_c_func(&mut c_func, too_long.moves.len(), 0)
+            .is_err());
+        assert!(main
+            .next_c_func(&mut c_func, fitting.moves.len(), 0)
+            .is_ok());
+        assert_eq!(c_func.to_ascii_string(), "R,9,3,L,9,3");
+    }
+
     #[test]
     fn test_opcode_string_creation() {
         use super::*;
@@ -269,7 +289,7 @@ mod tests {
         const C: Opcode = 0x43;
         const COMMA: Opcode = 0x2c;
 
-        let opcode_str = main.to_opcode_string();
+        let opcode_str: Vec<Opcode> = main.to_ascii_string().bytes().map(Opcode::from).collect();
         assert_eq!(
             opcode_str,
             vec![A, COMMA, B, COMMA, C, COMMA, B, COMMA, C, COMMA, A]
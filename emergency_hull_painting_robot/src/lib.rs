@@ -3,6 +3,7 @@
 use intcode_channel_io::IntcodeThread;
 use intcode_computer::{Opcode, ProgramMemory};
 use std::collections::HashMap;
+use std::io;
 
 /// Orientation of the robot.
 pub enum Orientation {
@@ -114,9 +115,22 @@ impl EmergencyHullPaintingRobot {
     /// since it will be passed onto the IntcodeThread, that transfers ownership
     /// into another thread.
     pub fn new(program: ProgramMemory) -> EmergencyHullPaintingRobot {
-        let painted_panels = HashMap::new();
         let identifier = Some(String::from("Robot"));
-        let thread = IntcodeThread::new(program, identifier);
+        Self::with_thread(IntcodeThread::new(program, identifier))
+    }
+
+    /// Like new, but the IO of the brain is recorded to the session file,
+    /// so that the session can be replayed without the robot (see intcode_computer::Replayer).
+    pub fn recorded(
+        program: ProgramMemory,
+        session_filename: &str,
+    ) -> io::Result<EmergencyHullPaintingRobot> {
+        let identifier = Some(String::from("Robot"));
+        IntcodeThread::recorded(program, identifier, session_filename).map(Self::with_thread)
+    }
+
+    fn with_thread(thread: IntcodeThread) -> EmergencyHullPaintingRobot {
+        let painted_panels = HashMap::new();
         let position = Coordinate(0, 0);
         let orientation = Orientation::Up;
         let moves = 0;
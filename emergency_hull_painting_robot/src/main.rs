@@ -3,13 +3,39 @@ extern crate emergency_hull_painting_robot;
 use emergency_hull_painting_robot::{Color, EmergencyHullPaintingRobot};
 use intcode_computer::read_program_from_file;
 
+const USAGE: &str = "usage: emergency_hull_painting_robot [--record <session>] [program]";
+
+/// Prints the usage and exits, for command lines that can not be understood.
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}\n{}", message, USAGE);
+    std::process::exit(2)
+}
+
 fn main() {
-    let mut args = std::env::args();
-    args.next();
-    let filename = args.next().unwrap_or(String::from("input.txt"));
+    // With `--record <session>`, the IO of the robot brain is recorded to the given file.
+    let mut session_filename = None;
+    let mut filename = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--record" {
+            match args.next() {
+                Some(session) => session_filename = Some(session),
+                None => usage_error("--record needs a session filename"),
+            }
+        } else if filename.is_none() {
+            filename = Some(arg);
+        } else {
+            usage_error(&format!("unexpected argument '{}'", arg));
+        }
+    }
+    let filename = filename.unwrap_or(String::from("input.txt"));
 
     let program = read_program_from_file(&filename);
-    let mut robot = EmergencyHullPaintingRobot::new(program);
+    let mut robot = match session_filename {
+        Some(session_filename) => EmergencyHullPaintingRobot::recorded(program, &session_filename)
+            .unwrap_or_else(|err| panic!("{}: {}", session_filename, err)),
+        None => EmergencyHullPaintingRobot::new(program),
+    };
     robot.run(Color::White);
     println!("{} moves", robot.moves);
     println!("{} field painted", robot.painted_panels.keys().len());
@@ -0,0 +1,296 @@
+//! Running Intcode programs as futures instead of threads, so that many of them can share a single executor.
+//! The futures do not depend on a particular executor, anything able to poll them works.
+
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::StreamExt;
+use intcode_computer::{IntcodeError, IoEvent, Limits, Machine, Memory, Opcode, ProgramMemory};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::Message;
+
+/// Number of instructions a program executes before it lets the executor run other futures.
+const INSTRUCTIONS_PER_POLL: u64 = 10_000;
+
+/// Pending once, so that the executor gets a chance to poll other futures before continuing.
+struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// The async counterpart to IntcodeChannelIo: reading waits for the host without blocking the thread.
+pub struct AsyncChannelIo {
+    sender: UnboundedSender<Message>,
+    receiver: UnboundedReceiver<Message>,
+}
+
+impl AsyncChannelIo {
+    pub fn new(sender: UnboundedSender<Message>, receiver: UnboundedReceiver<Message>) -> Self {
+        AsyncChannelIo { sender, receiver }
+    }
+
+    /// Waits for the next value from the host, fails if the host is gone.
+    pub async fn read(&mut self) -> Result<Opcode, String> {
+        match self.receiver.next().await {
+            Some(Message::Data(val)) => Ok(val),
+            Some(message) => Err(format!("unexpected message {:?}", message)),
+            None => Err(String::from("host is down")),
+        }
+    }
+
+    /// Sends a value to the host, the channel is unbounded, so this never waits.
+    pub fn write(&self, value: Opcode) -> Result<(), String> {
+        self.sender
+            .unbounded_send(Message::Data(value))
+            .map_err(|_| String::from("host is down"))
+    }
+
+    /// The host may be gone already, in that case nobody is interested in the signal.
+    pub fn send_exit_signal(&self) {
+        self.sender.unbounded_send(Message::Exited).ok();
+    }
+
+    pub fn send_failure(&self, err: IntcodeError) {
+        self.sender.unbounded_send(Message::Failed(err)).ok();
+    }
+}
+
+/// Run the machine until it halts, doing all IO through inout.
+/// This is Machine::run for async hosts: waiting for input and long computations do not block the executor.
+pub async fn run_async<M: Memory<Cell = Opcode>>(
+    machine: &mut Machine<M>,
+    inout: &mut AsyncChannelIo,
+) -> Result<(), IntcodeError> {
+    loop {
+        let event = machine.run_for(INSTRUCTIONS_PER_POLL)?;
+        let pc = machine.pc();
+        let io_error = |pc: usize, message| IntcodeError::Io {
+            pc,
+            opcode: machine.memory()[pc],
+            message,
+        };
+        match event {
+            None => YieldNow { yielded: false }.await,
+            // the input instruction is not executed yet, so pc still points at it
+            Some(IoEvent::NeedsInput) => match inout.read().await {
+                Ok(value) => machine.push_input(value),
+                Err(message) => return Err(io_error(pc, message)),
+            },
+            // the output instruction is executed already and has a single parameter
+            Some(IoEvent::Output(value)) => inout
+                .write(value)
+                .map_err(|message| io_error(pc - 2, message))?,
+            Some(IoEvent::Halted) => return Ok(()),
+        }
+    }
+}
+
+/// The interface for a program running as a future, the async counterpart to IntcodeThread.
+pub struct IntcodeTask {
+    sender: UnboundedSender<Message>,
+    receiver: UnboundedReceiver<Message>,
+    exited: bool,
+    error: Option<IntcodeError>,
+    pub identifier: String,
+    pub hide_debug_messages: bool,
+}
+
+impl IntcodeTask {
+    /// Sets up the channels to the program and returns the interface for the host together with the future,
+    /// that runs the program. The future has to be spawned on an executor and resolves to the final memory.
+    pub fn new(
+        program: ProgramMemory,
+        identifier: Option<String>,
+    ) -> (
+        IntcodeTask,
+        impl Future<Output = ProgramMemory> + Send + 'static,
+    ) {
+        Self::with_limits(program, identifier, Limits::none())
+    }
+
+    /// Like new, but the program stops once it exceeds one of the limits.
+    /// The task then counts as exited and the reason can be queried with error.
+    pub fn with_limits(
+        program: ProgramMemory,
+        identifier: Option<String>,
+        limits: Limits,
+    ) -> (
+        IntcodeTask,
+        impl Future<Output = ProgramMemory> + Send + 'static,
+    ) {
+        // set up bidirectional channel
+        let (host_sender, task_receiver) = mpsc::unbounded();
+        let (task_sender, host_receiver) = mpsc::unbounded();
+        let mut inout = AsyncChannelIo::new(task_sender, task_receiver);
+
+        let future = async move {
+            let mut machine = Machine::new(program);
+            machine.set_limits(limits);
+            match run_async(&mut machine, &mut inout).await {
+                Ok(()) => inout.send_exit_signal(),
+                Err(err) => inout.send_failure(err),
+            }
+            machine.into_memory()
+        };
+
+        let task = IntcodeTask {
+            sender: host_sender,
+            receiver: host_receiver,
+            exited: false,
+            error: None,
+            identifier: identifier.unwrap_or(String::from("Task ?")),
+            hide_debug_messages: false,
+        };
+        (task, future)
+    }
+
+    /// Sends an Opcode to the program, without waiting for it to be read.
+    pub fn send(&self, value: Opcode) {
+        if !self.hide_debug_messages {
+            println!("[{}]: sending <{}> to task", self.identifier, value);
+        }
+        self.sender
+            .unbounded_send(Message::Data(value))
+            .unwrap_or_else(|err| {
+                println!(
+                    "{}: task apparently down; couldn't send; err: '{}'",
+                    self.identifier, err
+                );
+            });
+    }
+
+    /// Waits for an Opcode from the program and maybe updates the exited field.
+    pub async fn recv(&mut self) -> Option<Opcode> {
+        match self.receiver.next().await {
+            Some(Message::Data(val)) => {
+                if !self.hide_debug_messages {
+                    println!("[{}]: received <{}> from task...", self.identifier, val);
+                }
+                Some(val)
+            }
+            Some(Message::Exited) => {
+                println!("[{}]: task has exited", self.identifier);
+                self.exited = true;
+                None
+            }
+            Some(Message::Failed(err)) => {
+                println!("[{}]: task has failed: {}", self.identifier, err);
+                self.exited = true;
+                self.error = Some(err);
+                None
+            }
+            Some(Message::Panicked(message)) => {
+                println!("[{}]: task has panicked: {}", self.identifier, message);
+                self.exited = true;
+                None
+            }
+            // the future was dropped before the program finished
+            None => {
+                self.exited = true;
+                None
+            }
+        }
+    }
+
+    /// The error that stopped the program, if it did not exit normally.
+    pub fn error(&self) -> Option<IntcodeError> {
+        self.error.clone()
+    }
+
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    pub fn clone_sender(&self) -> UnboundedSender<Message> {
+        self.sender.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::LocalPool;
+    use futures::task::LocalSpawnExt;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_many_tasks_on_one_thread() {
+        // Reads a value, outputs it doubled and halts.
+        let doubler: Vec<Opcode> = vec![3, 9, 102, 2, 9, 9, 4, 9, 99, 0];
+        let mut pool = LocalPool::new();
+        let spawner = pool.spawner();
+
+        let mut tasks = Vec::new();
+        for i in 0..50 {
+            let (mut task, future) = IntcodeTask::new(
+                doubler.iter().cloned().collect(),
+                Some(format!("Task {}", i)),
+            );
+            task.hide_debug_messages = true;
+            spawner.spawn_local(async { drop(future.await) }).unwrap();
+            tasks.push(task);
+        }
+
+        let outputs = pool.run_until(async {
+            let mut outputs = Vec::new();
+            for (i, task) in tasks.iter_mut().enumerate() {
+                task.send(i as Opcode);
+                outputs.push(task.recv().await);
+                assert_eq!(task.recv().await, None);
+                assert!(task.has_exited());
+                assert!(task.error().is_none());
+            }
+            outputs
+        });
+        assert_eq!(outputs, (0..50).map(|i| Some(2 * i)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_long_computation_yields() {
+        // Loops forever, until the instruction limit is reached.
+        let limits = Limits {
+            max_instructions: Some(100 * INSTRUCTIONS_PER_POLL),
+            ..Limits::none()
+        };
+        let (mut endless, endless_future) =
+            IntcodeTask::with_limits(vec![1105, 1, 0].into_iter().collect(), None, limits);
+        endless.hide_debug_messages = true;
+        let (mut task, future) = IntcodeTask::new(vec![104, 7, 99].into_iter().collect(), None);
+        task.hide_debug_messages = true;
+
+        let mut pool = LocalPool::new();
+        let spawner = pool.spawner();
+        let endless_finished = Rc::new(Cell::new(false));
+        let finished = Rc::clone(&endless_finished);
+        spawner
+            .spawn_local(async move {
+                endless_future.await;
+                finished.set(true);
+            })
+            .unwrap();
+        spawner.spawn_local(async { drop(future.await) }).unwrap();
+
+        // The other task gets its turn, while the endless one is still running.
+        assert_eq!(pool.run_until(task.recv()), Some(7));
+        assert!(!endless_finished.get());
+
+        pool.run();
+        assert!(endless_finished.get());
+        assert_eq!(pool.run_until(endless.recv()), None);
+        assert!(endless.error().is_some_and(|err| err.is_limit()));
+    }
+}
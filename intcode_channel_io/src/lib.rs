@@ -1,15 +1,69 @@
 //! IO over mpsc channels for the [Advent of Code 2019](adventofcode.com/2019) Intcode Computer.
 
-use intcode_computer::{run_program, IntcodeIo, Opcode, ProgramMemory};
+use intcode_computer::{
+    try_run_program_with_limits, IntcodeError, IntcodeIo, Limits, Machine, Opcode, ProgramMemory,
+    Recorder,
+};
+use std::any::Any;
 use std::cell::RefCell;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::mpsc;
 use std::thread;
+use std::time::Duration;
+
+mod async_io;
+pub use async_io::{run_async, AsyncChannelIo, IntcodeTask};
+
+mod scheduler;
+pub use scheduler::{MachineId, MachineState, Scheduler, SchedulerStatus};
 
 /// Message type to be sent between threads.
 #[derive(Debug)]
 pub enum Message {
     Data(Opcode),
     Exited,
+    /// The program was stopped by an error, for example because it exceeded its limits.
+    Failed(IntcodeError),
+    /// The worker thread panicked, with the panic message.
+    Panicked(String),
+}
+
+/// Why IntcodeThread::try_recv or IntcodeThread::recv_timeout did not return a value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecvError {
+    /// The worker did not send anything yet, but it is still running.
+    Empty,
+    /// The worker did not send anything within the timeout, but it is still running.
+    Timeout,
+    /// The program halted or was stopped by an error, see IntcodeThread::error.
+    Exited,
+    /// The worker thread panicked, with the panic message.
+    Panicked(String),
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvError::Empty => write!(f, "worker did not send anything yet"),
+            RecvError::Timeout => write!(f, "worker did not send anything in time"),
+            RecvError::Exited => write!(f, "worker has exited"),
+            RecvError::Panicked(message) => write!(f, "worker has panicked: {}", message),
+        }
+    }
+}
+
+/// The message of a panic, as far as it can be recovered from the payload.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        String::from(*message)
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("unknown panic")
+    }
 }
 
 /// Implementation of intcode_computer::IntcodeIo with mpsc channels.
@@ -26,22 +80,39 @@ impl IntcodeChannelIo {
         IntcodeChannelIo { sender, receiver }
     }
 
+    /// The host may be gone already, in that case nobody is interested in the signal.
     pub fn send_exit_signal(&self) {
-        self.sender.send(Message::Exited).unwrap();
+        self.sender.send(Message::Exited).ok();
+    }
+
+    pub fn send_failure(&self, err: IntcodeError) {
+        self.sender.send(Message::Failed(err)).ok();
     }
 }
 
+/// A host, that is gone or sends something other than data, stops the program with IntcodeError::Io.
 impl IntcodeIo for IntcodeChannelIo {
     fn read(&self) -> Opcode {
+        self.try_read().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    fn write(&self, value: &Opcode) {
+        self.try_write(value)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    fn try_read(&self) -> Result<Opcode, String> {
         match self.receiver.recv() {
-            Ok(Message::Data(val)) => val,
-            Err(_) => panic!("Error receiving, sender thread must be down..."),
-            _ => panic!("weird message"),
+            Ok(Message::Data(val)) => Ok(val),
+            Ok(message) => Err(format!("unexpected message {:?}", message)),
+            Err(_) => Err(String::from("host is down")),
         }
     }
 
-    fn write(&self, value: &Opcode) {
-        self.sender.send(Message::Data(value.clone())).unwrap();
+    fn try_write(&self, value: &Opcode) -> Result<(), String> {
+        self.sender
+            .send(Message::Data(*value))
+            .map_err(|_| String::from("host is down"))
     }
 }
 
@@ -51,21 +122,76 @@ pub struct IntcodeThread {
     sender: mpsc::Sender<Message>,
     receiver: mpsc::Receiver<Message>,
     exited: RefCell<bool>,
+    error: RefCell<Option<IntcodeError>>,
+    panic: RefCell<Option<String>>,
     pub identifier: String,
     pub hide_debug_messages: bool,
 }
 
 impl IntcodeThread {
-    pub fn new(mut program: ProgramMemory, identifier: Option<String>) -> IntcodeThread {
+    pub fn new(program: ProgramMemory, identifier: Option<String>) -> IntcodeThread {
+        Self::with_limits(program, identifier, Limits::none())
+    }
+
+    /// Like new, but the worker stops once the program exceeds one of the limits.
+    /// The thread then counts as exited and the reason can be queried with error.
+    pub fn with_limits(
+        mut program: ProgramMemory,
+        identifier: Option<String>,
+        limits: Limits,
+    ) -> IntcodeThread {
+        Self::spawn(identifier, move |inout| {
+            match try_run_program_with_limits(&mut program, &inout, limits) {
+                Ok(()) => inout.send_exit_signal(),
+                Err(err) => inout.send_failure(err),
+            }
+            program
+        })
+    }
+
+    /// Like new, but the worker records all IO to the session file, see intcode_computer::Recorder.
+    /// The session can be replayed without the host later on, for example with `intcode_computer replay`.
+    pub fn recorded(
+        program: ProgramMemory,
+        identifier: Option<String>,
+        session_filename: &str,
+    ) -> io::Result<IntcodeThread> {
+        let session = BufWriter::new(File::create(session_filename)?);
+        Ok(Self::spawn(identifier, move |inout| {
+            let mut machine = Machine::new(program);
+            let mut recorder = Recorder::new(inout, session);
+            let result = recorder.run(&mut machine);
+            let (inout, _) = recorder.into_inner();
+            match result {
+                Ok(()) => inout.send_exit_signal(),
+                Err(err) => inout.send_failure(err),
+            }
+            machine.into_memory()
+        }))
+    }
+
+    /// Sets up the channels and runs worker in a new thread, passing it the IO for the thread side.
+    fn spawn<F>(identifier: Option<String>, worker: F) -> IntcodeThread
+    where
+        F: FnOnce(IntcodeChannelIo) -> ProgramMemory + Send + 'static,
+    {
         // set up bidirectional channel
         let (host_sender, thread_receiver) = mpsc::channel();
         let (thread_sender, host_receiver) = mpsc::channel();
+        let panic_sender = thread_sender.clone();
         let inout = IntcodeChannelIo::new(thread_sender, thread_receiver);
 
+        // a panicking worker tells the host why, instead of just disappearing
         let handle = Some(thread::spawn(move || {
-            run_program(&mut program, &inout);
-            inout.send_exit_signal();
-            program
+            match panic::catch_unwind(AssertUnwindSafe(|| worker(inout))) {
+                Ok(program) => program,
+                Err(payload) => {
+                    panic_sender
+                        .send(Message::Panicked(panic_message(payload.as_ref())))
+                        .ok();
+                    panic::resume_unwind(payload)
+                }
+            }
         }));
 
         let identifier = identifier.unwrap_or(String::from("Thread ?"));
@@ -77,6 +203,8 @@ impl IntcodeThread {
             sender: host_sender,
             receiver: host_receiver,
             exited: RefCell::new(false),
+            error: RefCell::new(None),
+            panic: RefCell::new(None),
             identifier,
             hide_debug_messages,
         }
@@ -98,22 +226,90 @@ impl IntcodeThread {
     }
 
     /// Receives an Opcode from the worker and maybe update internal exited field.
+    /// Blocks until the worker sends something, returns None once it exited or panicked.
     pub fn recv(&self) -> Option<Opcode> {
-        match self.receiver.recv().unwrap() {
-            Message::Data(val) => {
+        self.receive(|receiver| receiver.recv().map_err(|_| RecvError::Exited))
+            .ok()
+    }
+
+    /// Like recv, but returns RecvError::Empty instead of blocking, if the worker did not send anything yet.
+    pub fn try_recv(&self) -> Result<Opcode, RecvError> {
+        self.receive(|receiver| {
+            receiver.try_recv().map_err(|err| match err {
+                mpsc::TryRecvError::Empty => RecvError::Empty,
+                mpsc::TryRecvError::Disconnected => RecvError::Exited,
+            })
+        })
+    }
+
+    /// Like recv, but returns RecvError::Timeout, if the worker did not send anything within timeout.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Opcode, RecvError> {
+        self.receive(|receiver| {
+            receiver.recv_timeout(timeout).map_err(|err| match err {
+                mpsc::RecvTimeoutError::Timeout => RecvError::Timeout,
+                mpsc::RecvTimeoutError::Disconnected => RecvError::Exited,
+            })
+        })
+    }
+
+    /// Receives a message with receive and handles it, updating the exited, error and panic fields.
+    /// Disconnected channels are passed as RecvError::Exited.
+    fn receive<F>(&self, receive: F) -> Result<Opcode, RecvError>
+    where
+        F: FnOnce(&mpsc::Receiver<Message>) -> Result<Message, RecvError>,
+    {
+        // nothing follows the last message of the worker, even if the channel is not disconnected yet
+        let message = if self.has_exited() {
+            Err(RecvError::Exited)
+        } else {
+            receive(&self.receiver)
+        };
+        match message {
+            Ok(Message::Data(val)) => {
                 if !self.hide_debug_messages {
                     println!("[{}]: received <{}> from worker...", self.identifier, val);
                 }
-                Some(val)
+                Ok(val)
             }
-            Message::Exited => {
+            Ok(Message::Exited) => {
                 println!("[{}]: worker has exited", self.identifier);
                 *self.exited.borrow_mut() = true;
-                None
+                Err(RecvError::Exited)
+            }
+            Ok(Message::Failed(err)) => {
+                println!("[{}]: worker has failed: {}", self.identifier, err);
+                *self.exited.borrow_mut() = true;
+                *self.error.borrow_mut() = Some(err);
+                Err(RecvError::Exited)
             }
+            Ok(Message::Panicked(message)) => {
+                println!("[{}]: worker has panicked: {}", self.identifier, message);
+                *self.exited.borrow_mut() = true;
+                *self.panic.borrow_mut() = Some(message.clone());
+                Err(RecvError::Panicked(message))
+            }
+            // the worker is gone, after telling why or without a word
+            Err(RecvError::Exited) => {
+                *self.exited.borrow_mut() = true;
+                match self.panic_message() {
+                    Some(message) => Err(RecvError::Panicked(message)),
+                    None => Err(RecvError::Exited),
+                }
+            }
+            Err(err) => Err(err),
         }
     }
 
+    /// The error that stopped the worker, if it did not exit normally.
+    pub fn error(&self) -> Option<IntcodeError> {
+        self.error.borrow().clone()
+    }
+
+    /// The panic message of the worker, if it panicked.
+    pub fn panic_message(&self) -> Option<String> {
+        self.panic.borrow().clone()
+    }
+
     /// Public getter for (internally mutable) "exited" field.
     pub fn has_exited(&self) -> bool {
         *self.exited.borrow()
@@ -136,3 +332,86 @@ impl IntcodeThread {
             .unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_limits() {
+        // Outputs 1 and loops forever afterwards.
+        let program = vec![104, 1, 1105, 1, 2].into_iter().collect();
+        let limits = Limits {
+            max_instructions: Some(100),
+            ..Limits::none()
+        };
+        let mut thread = IntcodeThread::with_limits(program, None, limits);
+        thread.hide_debug_messages = true;
+
+        assert_eq!(thread.recv(), Some(1));
+        assert_eq!(thread.recv(), None);
+        assert!(thread.has_exited());
+        assert!(thread.error().is_some_and(|err| err.is_limit()));
+        thread.join();
+    }
+
+    #[test]
+    fn test_try_recv() {
+        // Reads a value, outputs it and halts.
+        let program = vec![3, 5, 4, 5, 99, 0].into_iter().collect();
+        let mut thread = IntcodeThread::new(program, None);
+        thread.hide_debug_messages = true;
+
+        assert_eq!(thread.try_recv(), Err(RecvError::Empty));
+        assert_eq!(
+            thread.recv_timeout(Duration::from_millis(10)),
+            Err(RecvError::Timeout)
+        );
+        assert!(!thread.has_exited());
+
+        thread.send(42);
+        assert_eq!(thread.recv_timeout(Duration::from_secs(10)), Ok(42));
+        assert_eq!(
+            thread.recv_timeout(Duration::from_secs(10)),
+            Err(RecvError::Exited)
+        );
+        assert!(thread.has_exited());
+        // The worker is gone now, which does not change anything.
+        assert_eq!(thread.try_recv(), Err(RecvError::Exited));
+        thread.join();
+    }
+
+    #[test]
+    fn test_unexpected_message() {
+        // The host sends an exit signal instead of input, which stops the program.
+        let program = vec![3, 0, 99].into_iter().collect();
+        let mut thread = IntcodeThread::new(program, None);
+        thread.clone_sender().send(Message::Exited).unwrap();
+
+        assert_eq!(
+            thread.recv_timeout(Duration::from_secs(10)),
+            Err(RecvError::Exited)
+        );
+        match thread.error() {
+            Some(IntcodeError::Io { pc, message, .. }) => {
+                assert_eq!(pc, 0);
+                assert_eq!(message, "unexpected message Exited");
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+        assert_eq!(thread.panic_message(), None);
+        thread.join();
+    }
+
+    #[test]
+    fn test_worker_panicked() {
+        let thread = IntcodeThread::spawn(None, |_| panic!("worker failed"));
+
+        let expected = Err(RecvError::Panicked(String::from("worker failed")));
+        assert_eq!(thread.recv_timeout(Duration::from_secs(10)), expected);
+        assert!(thread.has_exited());
+        assert_eq!(thread.try_recv(), expected);
+        assert_eq!(thread.recv(), None);
+        assert_eq!(thread.panic_message(), Some(String::from("worker failed")));
+    }
+}
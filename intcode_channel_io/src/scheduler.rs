@@ -0,0 +1,287 @@
+//! Running many Intcode programs on a single thread, by giving each of them a slice of instructions in turn.
+
+use intcode_computer::{IntcodeError, IoEvent, Limits, Machine, Opcode, ProgramMemory};
+use std::collections::VecDeque;
+
+/// Default number of instructions a machine executes, before the next machine gets its turn.
+const INSTRUCTIONS_PER_SLICE: u64 = 10_000;
+
+/// Identifies a machine of a Scheduler, machines are numbered in the order they were added.
+pub type MachineId = usize;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MachineState {
+    /// The machine can execute further instructions.
+    Runnable,
+    /// The machine waits for input, that was not sent yet.
+    Blocked,
+    Halted,
+    /// The program was stopped by an error, for example because it exceeded its limits.
+    Failed(IntcodeError),
+}
+
+impl MachineState {
+    /// Whether the machine will never execute an instruction again.
+    pub fn is_finished(&self) -> bool {
+        matches!(self, MachineState::Halted | MachineState::Failed(_))
+    }
+}
+
+/// Why Scheduler::run_until_idle returned.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SchedulerStatus {
+    /// Every machine, that did not finish yet, is blocked on input.
+    Idle,
+    /// Every machine halted or failed.
+    Finished,
+}
+
+struct Scheduled {
+    machine: Machine,
+    identifier: String,
+    state: MachineState,
+    /// Outputs, that were not collected by the host yet.
+    output: VecDeque<Opcode>,
+    /// Machine, that receives the outputs instead of the host.
+    target: Option<MachineId>,
+}
+
+/// Runs many machines round-robin on the current thread, instead of one IntcodeThread each.
+/// Outputs are either collected for the host or moved to the input of another machine, see connect.
+pub struct Scheduler {
+    machines: Vec<Scheduled>,
+    slice: u64,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler {
+            machines: Vec::new(),
+            slice: INSTRUCTIONS_PER_SLICE,
+        }
+    }
+
+    /// Number of instructions a machine may execute per turn, a machine also ends its turn when it blocks.
+    pub fn set_slice(&mut self, slice: u64) {
+        self.slice = slice.max(1);
+    }
+
+    pub fn add(&mut self, program: ProgramMemory, identifier: Option<String>) -> MachineId {
+        self.add_with_limits(program, identifier, Limits::none())
+    }
+
+    /// Like add, but the machine fails once it exceeds one of the limits.
+    pub fn add_with_limits(
+        &mut self,
+        program: ProgramMemory,
+        identifier: Option<String>,
+        limits: Limits,
+    ) -> MachineId {
+        let id = self.machines.len();
+        let mut machine = Machine::new(program);
+        machine.set_limits(limits);
+        self.machines.push(Scheduled {
+            machine,
+            identifier: identifier.unwrap_or(format!("Machine {}", id)),
+            state: MachineState::Runnable,
+            output: VecDeque::new(),
+            target: None,
+        });
+        id
+    }
+
+    pub fn len(&self) -> usize {
+        self.machines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.machines.is_empty()
+    }
+
+    /// From now on, outputs of from are inputs of to. Outputs not collected yet are moved as well.
+    pub fn connect(&mut self, from: MachineId, to: MachineId) {
+        self.machines[from].target = Some(to);
+        let pending: Vec<Opcode> = self.machines[from].output.drain(..).collect();
+        for value in pending {
+            self.send(to, value);
+        }
+    }
+
+    /// Queues a value for the machine to read, which unblocks it.
+    pub fn send(&mut self, id: MachineId, value: Opcode) {
+        let scheduled = &mut self.machines[id];
+        scheduled.machine.push_input(value);
+        if scheduled.state == MachineState::Blocked {
+            scheduled.state = MachineState::Runnable;
+        }
+    }
+
+    /// Takes the oldest output of the machine, that was not collected yet.
+    pub fn recv(&mut self, id: MachineId) -> Option<Opcode> {
+        self.machines[id].output.pop_front()
+    }
+
+    /// Takes all outputs of the machine, that were not collected yet.
+    pub fn drain_output(&mut self, id: MachineId) -> Vec<Opcode> {
+        self.machines[id].output.drain(..).collect()
+    }
+
+    pub fn state(&self, id: MachineId) -> &MachineState {
+        &self.machines[id].state
+    }
+
+    pub fn identifier(&self, id: MachineId) -> &str {
+        &self.machines[id].identifier
+    }
+
+    pub fn machine(&self, id: MachineId) -> &Machine {
+        &self.machines[id].machine
+    }
+
+    /// Every machine gets one turn, if it is runnable.
+    /// Returns whether any machine was runnable, otherwise nothing can happen until the host sends something.
+    pub fn run_round(&mut self) -> bool {
+        let mut ran = false;
+        for id in 0..self.machines.len() {
+            if self.machines[id].state == MachineState::Runnable {
+                self.run_slice(id);
+                ran = true;
+            }
+        }
+        ran
+    }
+
+    /// Run rounds until every machine is either finished or blocked on input.
+    pub fn run_until_idle(&mut self) -> SchedulerStatus {
+        while self.run_round() {}
+        if self.machines.iter().all(|m| m.state.is_finished()) {
+            SchedulerStatus::Finished
+        } else {
+            SchedulerStatus::Idle
+        }
+    }
+
+    /// Run the machine until it blocks, finishes or used up its slice.
+    fn run_slice(&mut self, id: MachineId) {
+        let end = self.machines[id].machine.executed_instructions() + self.slice;
+        loop {
+            let scheduled = &mut self.machines[id];
+            let budget = end.saturating_sub(scheduled.machine.executed_instructions());
+            let value = match scheduled.machine.run_for(budget) {
+                Ok(None) => return,
+                Ok(Some(IoEvent::NeedsInput)) => {
+                    scheduled.state = MachineState::Blocked;
+                    return;
+                }
+                Ok(Some(IoEvent::Output(value))) => value,
+                Ok(Some(IoEvent::Halted)) => {
+                    scheduled.state = MachineState::Halted;
+                    return;
+                }
+                Err(err) => {
+                    scheduled.state = MachineState::Failed(err);
+                    return;
+                }
+            };
+            match scheduled.target {
+                Some(target) => self.send(target, value),
+                None => scheduled.output.push_back(value),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(values: &[Opcode]) -> ProgramMemory {
+        values.iter().cloned().collect()
+    }
+
+    #[test]
+    fn test_feedback_loop() {
+        // Second example of day 7
+        let amplifier = program(&[
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ]);
+        let mut scheduler = Scheduler::new();
+        let amplifiers: Vec<MachineId> = [9, 8, 7, 6, 5]
+            .iter()
+            .map(|&phase| {
+                let id = scheduler.add(amplifier.clone(), None);
+                scheduler.send(id, phase);
+                id
+            })
+            .collect();
+        for pair in amplifiers.windows(2) {
+            scheduler.connect(pair[0], pair[1]);
+        }
+
+        let (first, last) = (amplifiers[0], amplifiers[4]);
+        let mut signal = 0;
+        scheduler.send(first, signal);
+        while scheduler.run_until_idle() == SchedulerStatus::Idle {
+            signal = scheduler.recv(last).unwrap();
+            scheduler.send(first, signal);
+        }
+        assert_eq!(scheduler.recv(last), Some(139629729));
+        assert!(amplifiers
+            .iter()
+            .all(|&id| scheduler.state(id) == &MachineState::Halted));
+    }
+
+    #[test]
+    fn test_idle() {
+        // Outputs every input incremented by one, forever.
+        let echo = program(&[3, 9, 1001, 9, 1, 9, 4, 9, 1105, 1, 0]);
+        let mut scheduler = Scheduler::new();
+        for _ in 0..50 {
+            scheduler.add(echo.clone(), None);
+        }
+        assert_eq!(scheduler.run_until_idle(), SchedulerStatus::Idle);
+        assert_eq!(scheduler.state(7), &MachineState::Blocked);
+        assert!(!scheduler.run_round());
+
+        scheduler.send(7, 41);
+        scheduler.send(7, 1);
+        assert_eq!(scheduler.state(7), &MachineState::Runnable);
+        assert_eq!(scheduler.run_until_idle(), SchedulerStatus::Idle);
+        assert_eq!(scheduler.drain_output(7), vec![42, 2]);
+        assert_eq!(scheduler.recv(8), None);
+    }
+
+    #[test]
+    fn test_slices() {
+        // Loops forever, until the instruction limit is reached.
+        let limits = Limits {
+            max_instructions: Some(100),
+            ..Limits::none()
+        };
+        let mut scheduler = Scheduler::new();
+        scheduler.set_slice(10);
+        let endless = scheduler.add_with_limits(program(&[1105, 1, 0]), None, limits);
+        let printer = scheduler.add(program(&[104, 7, 99]), Some(String::from("printer")));
+        assert_eq!(scheduler.identifier(endless), "Machine 0");
+        assert_eq!(scheduler.identifier(printer), "printer");
+
+        // The printer finishes in the first round, even though the endless loop comes first.
+        assert!(scheduler.run_round());
+        assert_eq!(scheduler.machine(endless).executed_instructions(), 10);
+        assert_eq!(scheduler.state(printer), &MachineState::Halted);
+        assert_eq!(scheduler.recv(printer), Some(7));
+
+        assert_eq!(scheduler.run_until_idle(), SchedulerStatus::Finished);
+        match scheduler.state(endless) {
+            MachineState::Failed(err) => assert!(err.is_limit()),
+            state => panic!("unexpected state {:?}", state),
+        }
+    }
+}
@@ -12,13 +12,53 @@ op_lens = {
     6: 3,
     7: 4,
     8: 4,
+    9: 2,
 }
 
+mnemonics = {
+    1: "ADD",
+    2: "MUL",
+    99: "HLT",
+    3: "IN",
+    4: "OUT",
+    5: "JNZ",
+    6: "JZ",
+    7: "LT",
+    8: "EQ",
+    9: "ARB",
+}
+
+# instructions writing to their last parameter
+writing_instructions = (1, 2, 3, 7, 8)
+
+
+def trace_line(pc, opcode, relative_base, parameter_adrs, reads, write):
+    # same format as the LineTracer of the Rust implementation
+    line = "{} {} {} rb={} adr={} read={} write=".format(
+        pc,
+        opcode,
+        mnemonics[opcode % 100],
+        relative_base,
+        ",".join(map(str, parameter_adrs)),
+        ",".join(map(str, reads)),
+    )
+    if write is None:
+        return line + "-"
+    return line + "{}:{}".format(*write)
+
 
-def run_program(program):
+def grow(program, size):
+    # memory beyond the program is initialized with zeros
+    if size > len(program):
+        program.extend([0] * (size - len(program)))
+
+
+def run_program(program, trace=None):
     pc = 0
+    relative_base = 0
     while True:
         # Opcode parsing
+        grow(program, pc + 1)
         opcode = program[pc]
         instruction = opcode % 100
         modes = [
@@ -30,18 +70,33 @@ def run_program(program):
         # mode parsing and parameter loading
         parameter_adrs = list()
         op_len = op_lens[instruction]
+        grow(program, pc + op_len)
         for i in range(op_len - 1):
             if modes[i] == 0:
                 parameter_adrs.append(program[pc + i + 1])
             elif modes[i] == 1:
                 parameter_adrs.append(pc + 1 + i)
+            elif modes[i] == 2:
+                parameter_adrs.append(relative_base + program[pc + i + 1])
             else:
-                raise
+                raise ValueError("invalid mode in opcode {} at pc {}".format(opcode, pc))
+        if any(adr < 0 for adr in parameter_adrs):
+            raise ValueError("negative address in opcode {} at pc {}".format(opcode, pc))
+        grow(program, max(parameter_adrs, default=0) + 1)
 
         pc_jumped = False
+        opcode_pc = pc
+        opcode_relative_base = relative_base
+
+        # values of the parameters before execution, only needed for tracing
+        reads = [program[adr] for adr in parameter_adrs]
+        if instruction in writing_instructions:
+            reads.pop()
 
         # execution
         if instruction == 99:
+            if trace:
+                trace.write(trace_line(pc, opcode, relative_base, parameter_adrs, reads, None) + "\n")
             return program
         elif instruction == 1:
             p1 = program[parameter_adrs[0]]
@@ -72,13 +127,23 @@ def run_program(program):
         elif instruction == 7:
             p1 = program[parameter_adrs[0]]
             p2 = program[parameter_adrs[1]]
-            program[parameter_adrs[2]] = p1 < p2
+            program[parameter_adrs[2]] = int(p1 < p2)
         elif instruction == 8:
             p1 = program[parameter_adrs[0]]
             p2 = program[parameter_adrs[1]]
-            program[parameter_adrs[2]] = p1 == p2
+            program[parameter_adrs[2]] = int(p1 == p2)
+        elif instruction == 9:
+            relative_base += program[parameter_adrs[0]]
         else:
-            raise
+            raise ValueError("invalid opcode {} at pc {}".format(opcode, pc))
+
+        if trace:
+            write = None
+            if instruction in writing_instructions:
+                write = (parameter_adrs[-1], program[parameter_adrs[-1]])
+            trace.write(
+                trace_line(opcode_pc, opcode, opcode_relative_base, parameter_adrs, reads, write) + "\n"
+            )
 
         if not pc_jumped:
             pc += op_len
@@ -88,4 +153,16 @@ if __name__ == "__main__":
     filename = sys.argv[1]
     program = list(map(int, open(filename).read().split(",")))
 
-    run_program(program)
+    # options: intcode_computer.py <program> [--trace <tracefile>] [--memory <memoryfile>]
+    # --trace writes a line for every executed instruction,
+    # --memory writes the memory after the program stopped, even if it failed (for example because there was no input left)
+    options = dict(zip(sys.argv[2::2], sys.argv[3::2]))
+    trace = open(options["--trace"], "w") if "--trace" in options else None
+    try:
+        run_program(program, trace)
+    finally:
+        if trace:
+            trace.close()
+        if "--memory" in options:
+            with open(options["--memory"], "w") as memory:
+                memory.write(",".join(map(str, program)) + "\n")
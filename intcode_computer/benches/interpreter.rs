@@ -0,0 +1,108 @@
+//! Compares the reference interpreter with Machine, with and without its decode cache,
+//! on the BOOST program of day 9 and a full playthrough of the arcade game of day 13.
+
+use std::cell::{Cell, RefCell};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use intcode_computer::{
+    parse_program, reference, IntcodeError, IntcodeIo, Machine, Opcode, ProgramMemory,
+};
+
+/// Provides a constant input and keeps the last output.
+struct ConstantIo {
+    input: Opcode,
+    output: Cell<Opcode>,
+}
+
+impl IntcodeIo for ConstantIo {
+    fn read(&self) -> Opcode {
+        self.input
+    }
+
+    fn write(&self, value: &Opcode) {
+        self.output.set(*value);
+    }
+}
+
+/// Plays the arcade game by moving the paddle towards the ball.
+#[derive(Default)]
+struct ArcadeIo {
+    pending: RefCell<Vec<Opcode>>,
+    ball: Cell<Opcode>,
+    paddle: Cell<Opcode>,
+    score: Cell<Opcode>,
+}
+
+impl IntcodeIo for ArcadeIo {
+    fn read(&self) -> Opcode {
+        (self.ball.get() - self.paddle.get()).signum()
+    }
+
+    fn write(&self, value: &Opcode) {
+        let mut pending = self.pending.borrow_mut();
+        pending.push(*value);
+        if let [x, y, tile] = pending[..] {
+            match (x, y, tile) {
+                (-1, 0, score) => self.score.set(score),
+                (x, _, 3) => self.paddle.set(x),
+                (x, _, 4) => self.ball.set(x),
+                _ => (),
+            }
+            pending.clear();
+        }
+    }
+}
+
+fn run_machine<T: IntcodeIo>(
+    program: ProgramMemory,
+    inout: &T,
+    cached: bool,
+) -> Result<(), IntcodeError> {
+    let mut machine = Machine::new(program);
+    if cached {
+        machine.enable_decode_cache();
+    }
+    machine.run(inout)
+}
+
+fn bench_boost(c: &mut Criterion) {
+    let program = parse_program(include_str!("../../09/input.txt")).unwrap();
+    let mut group = c.benchmark_group("day 9 boost");
+    group.sample_size(10);
+
+    let io = || ConstantIo {
+        input: 2,
+        output: Cell::new(0),
+    };
+    group.bench_function("reference", |b| {
+        b.iter(|| reference::try_run_program(&mut program.clone(), &io()).unwrap())
+    });
+    group.bench_function("machine", |b| {
+        b.iter(|| run_machine(program.clone(), &io(), false).unwrap())
+    });
+    group.bench_function("machine with decode cache", |b| {
+        b.iter(|| run_machine(program.clone(), &io(), true).unwrap())
+    });
+    group.finish();
+}
+
+fn bench_arcade(c: &mut Criterion) {
+    let mut program = parse_program(include_str!("../../13/input.txt")).unwrap();
+    // play for free
+    program[0] = 2;
+    let mut group = c.benchmark_group("day 13 arcade");
+
+    group.bench_function("reference", |b| {
+        b.iter(|| reference::try_run_program(&mut program.clone(), &ArcadeIo::default()).unwrap())
+    });
+    group.bench_function("machine", |b| {
+        b.iter(|| run_machine(program.clone(), &ArcadeIo::default(), false).unwrap())
+    });
+    group.bench_function("machine with decode cache", |b| {
+        b.iter(|| run_machine(program.clone(), &ArcadeIo::default(), true).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_boost, bench_arcade);
+criterion_main!(benches);
@@ -0,0 +1,252 @@
+//! Ready-made IO for running programs without stdin or threads, mostly useful for tests.
+//! All of them implement IntcodeIoMut for any Cell type and are meant to be passed by value or as `&mut`.
+//! Where an IntcodeIo is needed (only for Opcodes), wrap them in a RefCell.
+
+use std::collections::VecDeque;
+use std::io::Write;
+
+use crate::{Cell, IntcodeIoMut, Opcode};
+
+/// The error of VecIo and FnIo once they have no input left, which makes ChainIo switch to its second IO.
+pub const NO_INPUT_LEFT: &str = "no input left";
+
+/// Reads from a queue of inputs and collects all outputs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VecIo<C = Opcode> {
+    input: VecDeque<C>,
+    output: Vec<C>,
+}
+
+impl<C: Cell> VecIo<C> {
+    pub fn new<I: IntoIterator<Item = C>>(input: I) -> Self {
+        Self {
+            input: input.into_iter().collect(),
+            output: Vec::new(),
+        }
+    }
+
+    /// Queues a value to be read after all other queued values.
+    pub fn push_input(&mut self, value: C) {
+        self.input.push_back(value);
+    }
+
+    /// All values written by the program so far.
+    pub fn output(&self) -> &[C] {
+        &self.output
+    }
+
+    pub fn into_output(self) -> Vec<C> {
+        self.output
+    }
+}
+
+impl<C: Cell> IntcodeIoMut<C> for VecIo<C> {
+    fn input(&mut self) -> Result<C, String> {
+        self.input
+            .pop_front()
+            .ok_or_else(|| String::from(NO_INPUT_LEFT))
+    }
+
+    fn output(&mut self, value: C) -> Result<(), String> {
+        self.output.push(value);
+        Ok(())
+    }
+}
+
+/// IO through two closures, the first returning None once there is no input left.
+pub struct FnIo<R, W> {
+    read: R,
+    write: W,
+}
+
+impl<R, W> FnIo<R, W> {
+    pub fn new(read: R, write: W) -> Self {
+        Self { read, write }
+    }
+}
+
+impl<C, R, W> IntcodeIoMut<C> for FnIo<R, W>
+where
+    C: Cell,
+    R: FnMut() -> Option<C>,
+    W: FnMut(C),
+{
+    fn input(&mut self) -> Result<C, String> {
+        (self.read)().ok_or_else(|| String::from(NO_INPUT_LEFT))
+    }
+
+    fn output(&mut self, value: C) -> Result<(), String> {
+        (self.write)(value);
+        Ok(())
+    }
+}
+
+/// Forwards to another IO and logs every value passing through,
+/// as a line `< value` for inputs and `> value` for outputs.
+pub struct TeeIo<T, L: Write> {
+    inner: T,
+    log: L,
+}
+
+impl<T, L: Write> TeeIo<T, L> {
+    pub fn new(inner: T, log: L) -> Self {
+        Self { inner, log }
+    }
+
+    pub fn into_inner(self) -> (T, L) {
+        (self.inner, self.log)
+    }
+}
+
+impl<C: Cell, T: IntcodeIoMut<C>, L: Write> IntcodeIoMut<C> for TeeIo<T, L> {
+    fn input(&mut self) -> Result<C, String> {
+        let value = self.inner.input()?;
+        writeln!(self.log, "< {}", value).map_err(|err| err.to_string())?;
+        Ok(value)
+    }
+
+    fn output(&mut self, value: C) -> Result<(), String> {
+        writeln!(self.log, "> {}", value).map_err(|err| err.to_string())?;
+        self.inner.output(value)
+    }
+}
+
+/// Reads from the first IO until it has no input left (it fails with NO_INPUT_LEFT) and from the second afterwards.
+/// Other errors of the first IO are passed on. All outputs are written to the second IO.
+pub struct ChainIo<A, B> {
+    first: Option<A>,
+    second: B,
+}
+
+impl<A, B> ChainIo<A, B> {
+    pub fn new(first: A, second: B) -> Self {
+        Self {
+            first: Some(first),
+            second,
+        }
+    }
+
+    pub fn into_inner(self) -> B {
+        self.second
+    }
+}
+
+impl<C: Cell, A: IntcodeIoMut<C>, B: IntcodeIoMut<C>> IntcodeIoMut<C> for ChainIo<A, B> {
+    fn input(&mut self) -> Result<C, String> {
+        if let Some(first) = self.first.as_mut() {
+            match first.input() {
+                Ok(value) => return Ok(value),
+                // the first source is exhausted, it is not asked again
+                Err(err) if err == NO_INPUT_LEFT => self.first = None,
+                Err(err) => return Err(err),
+            }
+        }
+        self.second.input()
+    }
+
+    fn output(&mut self, value: C) -> Result<(), String> {
+        self.second.output(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_program, try_run_program, BigInt, BigProgramMemory, ProgramMemory};
+    use std::cell::RefCell;
+
+    fn diagnostics() -> ProgramMemory {
+        parse_program(include_str!("../input.txt")).unwrap()
+    }
+
+    #[test]
+    fn test_vec_io() {
+        // The diagnostic program of day 5 outputs zeros for passed tests, followed by the diagnostic code.
+        let mut io = VecIo::new(vec![1]);
+        try_run_program(&mut diagnostics(), &mut io).unwrap();
+        let (code, tests) = io.output().split_last().unwrap();
+        assert!(tests.iter().all(|&test| test == 0));
+        assert_ne!(*code, 0);
+
+        let mut io = VecIo::new(vec![5]);
+        try_run_program(&mut diagnostics(), &mut io).unwrap();
+        assert_eq!(io.output().len(), 1);
+
+        // Running out of input is an error.
+        assert!(try_run_program(&mut diagnostics(), VecIo::default()).is_err());
+    }
+
+    #[test]
+    fn test_fn_io() {
+        let mut inputs = vec![5].into_iter();
+        let mut outputs = Vec::new();
+        let io = FnIo::new(|| inputs.next(), |value| outputs.push(value));
+        try_run_program(&mut diagnostics(), io).unwrap();
+        assert_eq!(outputs.len(), 1);
+    }
+
+    #[test]
+    fn test_tee_and_chain_io() {
+        // Echoes inputs until it reads a zero.
+        let echo: ProgramMemory = vec![3, 9, 4, 9, 1005, 9, 0, 99, 0, 0].into_iter().collect();
+
+        let io = ChainIo::new(VecIo::new(vec![1, 2]), VecIo::new(vec![3, 0]));
+        let mut io = TeeIo::new(io, Vec::new());
+        try_run_program(&mut echo.clone(), &mut io).unwrap();
+
+        let (chain, log) = io.into_inner();
+        assert_eq!(chain.into_inner().into_output(), vec![1, 2, 3, 0]);
+        assert_eq!(
+            String::from_utf8(log).unwrap(),
+            "< 1\n> 1\n< 2\n> 2\n< 3\n> 3\n< 0\n> 0\n"
+        );
+    }
+
+    #[test]
+    fn test_big_cells() {
+        // Squares its input.
+        let mut squares: BigProgramMemory = vec![3, 9, 2, 9, 9, 9, 4, 9, 99, 0]
+            .into_iter()
+            .map(BigInt::from)
+            .collect();
+        let input = BigInt::from(3).pow(40);
+
+        let mut io = TeeIo::new(VecIo::new(vec![input.clone()]), Vec::new());
+        try_run_program(&mut squares, &mut io).unwrap();
+        let (io, log) = io.into_inner();
+        assert_eq!(io.into_output(), vec![BigInt::from(3).pow(80)]);
+        assert!(String::from_utf8(log)
+            .unwrap()
+            .starts_with(&format!("< {}\n", input)));
+    }
+
+    #[test]
+    fn test_ref_cell_bridge() {
+        // The adapters are IntcodeIoMut, a RefCell turns them into an IntcodeIo.
+        let io = RefCell::new(VecIo::new(vec![5]));
+        try_run_program(&mut diagnostics(), &io).unwrap();
+        assert_eq!(io.into_inner().output().len(), 1);
+    }
+
+    #[test]
+    fn test_chain_io_passes_on_errors() {
+        /// A log, that can not be written to.
+        struct Broken;
+
+        impl Write for Broken {
+            fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("log is broken"))
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut io = ChainIo::new(TeeIo::new(VecIo::new(vec![1]), Broken), VecIo::new(vec![2]));
+        assert_eq!(
+            IntcodeIoMut::<Opcode>::input(&mut io),
+            Err(String::from("log is broken"))
+        );
+    }
+}
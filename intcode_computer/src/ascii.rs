@@ -0,0 +1,133 @@
+use std::mem;
+
+use crate::{IntcodeError, IoEvent, Machine, Memory, Opcode, ProgramMemory};
+
+/// What a program speaking ASCII did next, as returned by AsciiIo::read_line.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AsciiEvent {
+    /// A complete line of output, without the newline.
+    Line(String),
+    /// A value outside of ASCII, which programs use to report results (like the collected dust on day 17).
+    NonAscii(Opcode),
+    /// The program wants to read, but all lines written so far are consumed already.
+    /// Output since the last newline (like a prompt) stays buffered until the line is complete.
+    NeedsInput,
+    /// The program has halted, after any remaining output was returned as a Line.
+    Halted,
+}
+
+/// Line based text IO with a program, which reads and writes ASCII characters as single values.
+pub struct AsciiIo<M: Memory = ProgramMemory> {
+    machine: Machine<M>,
+    // output since the last newline
+    line: String,
+}
+
+impl<M: Memory<Cell = Opcode>> AsciiIo<M> {
+    pub fn new(program: M) -> Self {
+        Self::from_machine(Machine::new(program))
+    }
+
+    pub fn from_machine(machine: Machine<M>) -> Self {
+        Self {
+            machine,
+            line: String::new(),
+        }
+    }
+
+    pub fn machine(&self) -> &Machine<M> {
+        &self.machine
+    }
+
+    pub fn machine_mut(&mut self) -> &mut Machine<M> {
+        &mut self.machine
+    }
+
+    pub fn into_machine(self) -> Machine<M> {
+        self.machine
+    }
+
+    /// Run the program until it completes a line, outputs a non-ASCII value, needs input or halts.
+    pub fn read_line(&mut self) -> Result<AsciiEvent, IntcodeError> {
+        loop {
+            match self.machine.run_until_io()? {
+                IoEvent::Output(10) => return Ok(AsciiEvent::Line(mem::take(&mut self.line))),
+                IoEvent::Output(value @ 0..=127) => self.line.push(value as u8 as char),
+                IoEvent::Output(value) => return Ok(AsciiEvent::NonAscii(value)),
+                IoEvent::NeedsInput => return Ok(AsciiEvent::NeedsInput),
+                IoEvent::Halted if !self.line.is_empty() => {
+                    return Ok(AsciiEvent::Line(mem::take(&mut self.line)))
+                }
+                IoEvent::Halted => return Ok(AsciiEvent::Halted),
+            }
+        }
+    }
+
+    /// Collect lines until the program does anything else, which is returned as well.
+    pub fn read_lines(&mut self) -> Result<(Vec<String>, AsciiEvent), IntcodeError> {
+        let mut lines = Vec::new();
+        loop {
+            match self.read_line()? {
+                AsciiEvent::Line(line) => lines.push(line),
+                event => return Ok((lines, event)),
+            }
+        }
+    }
+
+    /// Queue a line of input, a newline is appended.
+    /// Fails without queueing anything, if the line contains characters outside of ASCII.
+    pub fn write_line(&mut self, line: &str) -> Result<(), String> {
+        if !line.is_ascii() {
+            return Err(format!("'{}' contains non-ASCII characters", line));
+        }
+        for byte in line.bytes().chain(Some(b'\n')) {
+            self.machine.push_input(byte.into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble;
+
+    /// Greets with a prompt, then reads a line, echoes it back and outputs its length.
+    const ECHO: &str = "
+      OUT #62
+      OUT #10
+loop: IN -> [char]
+      OUT [char]
+      EQ [char], #10 -> [done]
+      JNZ [done], #end
+      ADD [len], #1 -> [len]
+      JZ #0, #loop
+end:  OUT #200
+      ADD [len], #200 -> [len]
+      OUT [len]
+      OUT #33
+      HLT
+char: DATA 0
+done: DATA 0
+len:  DATA 0
+";
+
+    #[test]
+    fn test_ascii_io() {
+        let mut io = AsciiIo::new(assemble(ECHO).unwrap());
+        assert_eq!(io.read_line(), Ok(AsciiEvent::Line(String::from(">"))));
+        assert_eq!(io.read_line(), Ok(AsciiEvent::NeedsInput));
+
+        io.write_line("hello").unwrap();
+        assert_eq!(
+            io.read_lines(),
+            Ok((vec![String::from("hello")], AsciiEvent::NonAscii(200)))
+        );
+        assert_eq!(io.read_line(), Ok(AsciiEvent::NonAscii(205)));
+        // The last line is returned even without a newline.
+        assert_eq!(io.read_line(), Ok(AsciiEvent::Line(String::from("!"))));
+        assert_eq!(io.read_line(), Ok(AsciiEvent::Halted));
+
+        assert!(io.write_line("grüße").is_err());
+    }
+}
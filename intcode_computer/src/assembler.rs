@@ -0,0 +1,327 @@
+//! A small assembly language for writing Intcode programs by hand.
+//!
+//! Every line holds at most one instruction or data directive, optionally preceded by labels.
+//! The syntax mirrors the listings produced by `disassemble`:
+//!
+//! ```text
+//! ; Reads numbers and outputs them doubled, until a zero is read.
+//! loop: IN -> [value]
+//!       JZ [value], #end
+//!       MUL [value], #2 -> [value]
+//!       OUT [value]
+//!       JZ #0, #loop
+//! end:  HLT
+//! value: DATA 0
+//! ```
+//!
+//! Parameters are written as `[100]` (position mode), `#5` (immediate mode) or `[r+3]` (relative mode).
+//! Labels can be used instead of numbers in position and immediate mode and in data directives.
+//! The parameter an instruction writes to follows the other parameters after a `->`.
+//! Everything after a `;` is a comment.
+//! Lines may start with their address (like `0012:`), which is checked against the actual address.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::instruction::instruction_for_mnemonic;
+use crate::{Instruction, Mode, Opcode, ProgramMemory};
+
+/// An error in the assembly source, with the (1-based) line it occured in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssemblerError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AssemblerError {}
+
+/// A value in the source, which is either given directly or as the address of a label.
+enum Value {
+    Number(Opcode),
+    Label(String),
+}
+
+fn is_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => (),
+        _ => return false,
+    }
+    // `r` is reserved for the relative base.
+    name != "r" && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_number(s: &str) -> Result<Opcode, String> {
+    s.parse().map_err(|_| format!("invalid number '{}'", s))
+}
+
+fn parse_value(s: &str) -> Result<Value, String> {
+    if is_label_name(s) {
+        Ok(Value::Label(s.to_string()))
+    } else {
+        parse_number(s).map(Value::Number)
+    }
+}
+
+fn parse_parameter(s: &str) -> Result<(Mode, Value), String> {
+    if let Some(value) = s.strip_prefix('#') {
+        return Ok((Mode::Immediate, parse_value(value.trim())?));
+    }
+
+    let inner = s
+        .strip_prefix('[')
+        .and_then(|s| s.strip_suffix(']'))
+        .ok_or_else(|| format!("invalid parameter '{}'", s))?;
+    let inner: String = inner.chars().filter(|c| !c.is_whitespace()).collect();
+
+    if inner == "r" {
+        Ok((Mode::Relative, Value::Number(0)))
+    } else if let Some(offset) = inner.strip_prefix("r+") {
+        Ok((Mode::Relative, Value::Number(parse_number(offset)?)))
+    } else if inner.starts_with("r-") {
+        Ok((Mode::Relative, Value::Number(parse_number(&inner[1..])?)))
+    } else {
+        Ok((Mode::Position, parse_value(&inner)?))
+    }
+}
+
+/// Splits a comma seperated list, yielding nothing for an empty string.
+fn split_list(s: &str) -> Vec<&str> {
+    let s = s.trim();
+    if s.is_empty() {
+        Vec::new()
+    } else {
+        s.split(',').map(|part| part.trim()).collect()
+    }
+}
+
+/// Collects the assembled values and the places where labels have to be filled in later.
+#[derive(Default)]
+struct Assembler {
+    values: Vec<Opcode>,
+    labels: HashMap<String, usize>,
+    // address to fill in, label name and line of the reference
+    fixups: Vec<(usize, String, usize)>,
+}
+
+impl Assembler {
+    fn push(&mut self, value: Value, line: usize) {
+        match value {
+            Value::Number(n) => self.values.push(n),
+            Value::Label(name) => {
+                self.fixups.push((self.values.len(), name, line));
+                self.values.push(0);
+            }
+        }
+    }
+
+    /// Assembles everything of a line after its labels.
+    fn statement(&mut self, statement: &str, line: usize) -> Result<(), String> {
+        let (mnemonic, rest) = match statement.find(char::is_whitespace) {
+            Some(i) => (&statement[..i], &statement[i..]),
+            None => (statement, ""),
+        };
+
+        if mnemonic.eq_ignore_ascii_case("data") {
+            let values = split_list(rest);
+            if values.is_empty() {
+                return Err(String::from("data directive without values"));
+            }
+            for value in values {
+                let value = parse_value(value)?;
+                self.push(value, line);
+            }
+            return Ok(());
+        }
+
+        let instruction = instruction_for_mnemonic(mnemonic)
+            .ok_or_else(|| format!("unknown mnemonic '{}'", mnemonic))?;
+        // Decoding the bare instruction (all parameters in position mode) always works.
+        let template = Instruction::decode(instruction.into(), 0).unwrap();
+
+        let (reads, write) = match rest.find("->") {
+            Some(i) => (&rest[..i], Some(rest[i + 2..].trim())),
+            None => (rest, None),
+        };
+        let mut parameters = split_list(reads);
+        match (template.write_parameter(), write) {
+            (Some(i), Some(write)) => parameters.insert(i.min(parameters.len()), write),
+            (Some(_), None) => return Err(format!("{} needs a '->' parameter", mnemonic)),
+            (None, Some(_)) => return Err(format!("{} does not write to memory", mnemonic)),
+            (None, None) => (),
+        }
+        if parameters.len() != template.parameter_count() {
+            return Err(format!(
+                "{} takes {} parameters, but got {}",
+                mnemonic,
+                template.parameter_count(),
+                parameters.len()
+            ));
+        }
+
+        let parameters = parameters
+            .into_iter()
+            .map(parse_parameter)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut opcode = Opcode::from(instruction);
+        let mut factor = 100;
+        for (i, (mode, _)) in parameters.iter().enumerate() {
+            if *mode == Mode::Immediate && template.write_parameter() == Some(i) {
+                return Err(format!("{} cannot write in immediate mode", mnemonic));
+            }
+            opcode += factor
+                * match mode {
+                    Mode::Position => 0,
+                    Mode::Immediate => 1,
+                    Mode::Relative => 2,
+                };
+            factor *= 10;
+        }
+
+        self.values.push(opcode);
+        for (_, value) in parameters {
+            self.push(value, line);
+        }
+        Ok(())
+    }
+
+    fn line(&mut self, mut source: &str, line: usize) -> Result<(), String> {
+        if let Some(i) = source.find(';') {
+            source = &source[..i];
+        }
+
+        // Leading labels and addresses, both ending with a colon.
+        while let Some(i) = source.find(':') {
+            let name = source[..i].trim();
+            if !name.is_empty() && name.chars().all(|c| c.is_ascii_digit()) {
+                let address: usize = name.parse().unwrap();
+                if address != self.values.len() {
+                    return Err(format!(
+                        "line is marked with address {}, but is at address {}",
+                        address,
+                        self.values.len()
+                    ));
+                }
+            } else if is_label_name(name) {
+                if self
+                    .labels
+                    .insert(name.to_string(), self.values.len())
+                    .is_some()
+                {
+                    return Err(format!("label '{}' is defined twice", name));
+                }
+            } else {
+                return Err(format!("invalid label '{}'", name));
+            }
+            source = &source[i + 1..];
+        }
+
+        let statement = source.trim();
+        if statement.is_empty() {
+            Ok(())
+        } else {
+            self.statement(statement, line)
+        }
+    }
+}
+
+/// Turn assembly source into a program, that can be run with run_program.
+pub fn assemble(source: &str) -> Result<ProgramMemory, AssemblerError> {
+    let mut assembler = Assembler::default();
+
+    for (i, line) in source.lines().enumerate() {
+        assembler
+            .line(line, i + 1)
+            .map_err(|message| AssemblerError {
+                line: i + 1,
+                message,
+            })?;
+    }
+
+    // Now that all labels are known, the references to them can be resolved.
+    let Assembler {
+        mut values,
+        labels,
+        fixups,
+    } = assembler;
+    for (address, name, line) in fixups {
+        let target = labels.get(&name).ok_or_else(|| AssemblerError {
+            line,
+            message: format!("unknown label '{}'", name),
+        })?;
+        values[address] = *target as Opcode;
+    }
+
+    Ok(values.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{disassemble, IoEvent, Machine};
+
+    const DOUBLER: &str = "
+; Reads numbers and outputs them doubled, until a zero is read.
+loop: IN -> [value]
+      JZ [value], #end
+      MUL [value], #2 -> [value]
+      OUT [value]
+      JZ #0, #loop
+end:  HLT
+value: DATA 0
+";
+
+    #[test]
+    fn test_assemble() {
+        let program = assemble(DOUBLER).unwrap();
+        assert_eq!(
+            program.iter().cloned().collect::<Vec<_>>(),
+            vec![3, 15, 1006, 15, 14, 1002, 15, 2, 15, 4, 15, 1106, 0, 0, 99, 0]
+        );
+
+        let mut machine = Machine::new(program);
+        machine.push_input(21);
+        machine.push_input(0);
+        assert_eq!(machine.run_until_io(), Ok(IoEvent::Output(42)));
+        assert_eq!(machine.run_until_io(), Ok(IoEvent::Halted));
+    }
+
+    #[test]
+    fn test_relative_mode() {
+        let program = assemble("ARB #10\nADD [r-2], #3 -> [r + 1]\nOUT [r]\nHLT").unwrap();
+        assert_eq!(
+            program.iter().cloned().collect::<Vec<_>>(),
+            vec![109, 10, 21201, -2, 3, 1, 204, 0, 99]
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let program = crate::parse_program(include_str!("../../09/input.txt")).unwrap();
+        let reassembled = assemble(&disassemble(&program)).unwrap();
+        assert_eq!(
+            reassembled.iter().collect::<Vec<_>>(),
+            program.iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |source| assemble(source).unwrap_err();
+        assert_eq!(error("HLT\nFOO #1").line, 2);
+        assert_eq!(error("ADD #1, #2 -> #3").line, 1);
+        assert_eq!(error("ADD #1 -> [3]").line, 1);
+        assert_eq!(error("OUT #1 -> [2]").line, 1);
+        assert_eq!(error("JZ #0, #nowhere").line, 1);
+        assert_eq!(error("a: HLT\na: HLT").line, 2);
+        assert_eq!(error("0001: HLT").line, 1);
+    }
+}
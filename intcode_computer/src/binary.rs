@@ -0,0 +1,335 @@
+//! A compact binary format for programs and snapshots.
+//!
+//! Every file starts with the magic bytes `ICB`, a format version, the kind of content and a flags byte.
+//! All numbers are LEB128 varints, signed values are zigzag encoded first, so that small negative values stay small.
+//! If the checksum flag is set, the file ends with the 32 bit FNV-1a hash (little endian) of everything before it.
+//!
+//! A program is stored as its length followed by its values, a snapshot as its program counter, relative base,
+//! halted flag (a single byte), queued input and memory, the latter two again as length and values.
+//! Trailing zeros of the memory are not stored.
+
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+use crate::{Opcode, ProgramMemory, Snapshot};
+
+const MAGIC: &[u8; 3] = b"ICB";
+const VERSION: u8 = 1;
+
+const KIND_PROGRAM: u8 = 0;
+const KIND_SNAPSHOT: u8 = 1;
+
+const FLAG_CHECKSUM: u8 = 1;
+
+const HEADER_LEN: usize = 6;
+const CHECKSUM_LEN: usize = 4;
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// 32 bit FNV-1a, which is plenty to detect corrupted files.
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, &byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+fn push_unsigned(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn push_signed(bytes: &mut Vec<u8>, value: Opcode) {
+    push_unsigned(bytes, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+fn push_values<'a>(bytes: &mut Vec<u8>, values: impl ExactSizeIterator<Item = &'a Opcode>) {
+    push_unsigned(bytes, values.len() as u64);
+    for &value in values {
+        push_signed(bytes, value);
+    }
+}
+
+/// Number of values worth storing, that is without trailing zeros.
+fn trimmed_len(memory: &ProgramMemory) -> usize {
+    memory
+        .iter()
+        .rposition(|&value| value != 0)
+        .map_or(0, |i| i + 1)
+}
+
+/// Adds the header in front of and the optional checksum after the encoded content.
+fn write_file<W: Write>(
+    mut writer: W,
+    kind: u8,
+    content: &[u8],
+    with_checksum: bool,
+) -> io::Result<()> {
+    let flags = if with_checksum { FLAG_CHECKSUM } else { 0 };
+    let mut bytes = Vec::with_capacity(HEADER_LEN + content.len() + CHECKSUM_LEN);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&[VERSION, kind, flags]);
+    bytes.extend_from_slice(content);
+    if with_checksum {
+        let hash = checksum(&bytes);
+        bytes.extend_from_slice(&hash.to_le_bytes());
+    }
+    writer.write_all(&bytes)?;
+    writer.flush()
+}
+
+/// Reads a whole file, checks its header and checksum and returns the content.
+fn read_file<R: Read>(mut reader: R, expected_kind: u8) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    if bytes.len() < HEADER_LEN || &bytes[..3] != MAGIC {
+        return Err(invalid_data(String::from("not a binary intcode file")));
+    }
+    let (version, kind, flags) = (bytes[3], bytes[4], bytes[5]);
+    if version != VERSION {
+        return Err(invalid_data(format!("unsupported version {}", version)));
+    }
+    if kind != expected_kind {
+        let name = |kind| match kind {
+            KIND_PROGRAM => "program",
+            KIND_SNAPSHOT => "snapshot",
+            _ => "unknown content",
+        };
+        return Err(invalid_data(format!(
+            "expected a {}, but found a {}",
+            name(expected_kind),
+            name(kind)
+        )));
+    }
+
+    let mut end = bytes.len();
+    if flags & FLAG_CHECKSUM != 0 {
+        if end < HEADER_LEN + CHECKSUM_LEN {
+            return Err(invalid_data(String::from("missing checksum")));
+        }
+        end -= CHECKSUM_LEN;
+        let mut stored = [0; CHECKSUM_LEN];
+        stored.copy_from_slice(&bytes[end..]);
+        if u32::from_le_bytes(stored) != checksum(&bytes[..end]) {
+            return Err(invalid_data(String::from("checksum mismatch")));
+        }
+    }
+
+    bytes.truncate(end);
+    bytes.drain(..HEADER_LEN);
+    Ok(bytes)
+}
+
+/// Decodes the content of a file value by value.
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        let byte = *self
+            .bytes
+            .get(self.position)
+            .ok_or_else(|| invalid_data(String::from("unexpected end of file")))?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn unsigned(&mut self) -> io::Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(invalid_data(format!(
+            "varint at byte {} is too long",
+            self.position
+        )))
+    }
+
+    fn signed(&mut self) -> io::Result<Opcode> {
+        let value = self.unsigned()?;
+        Ok((value >> 1) as Opcode ^ -((value & 1) as Opcode))
+    }
+
+    fn usize(&mut self) -> io::Result<usize> {
+        let value = self.unsigned()?;
+        value
+            .try_into()
+            .map_err(|_| invalid_data(format!("value {} is too large", value)))
+    }
+
+    fn values(&mut self) -> io::Result<Vec<Opcode>> {
+        let len = self.usize()?;
+        // Every value takes at least one byte, which protects against huge allocations for corrupted lengths.
+        if len > self.bytes.len() - self.position {
+            return Err(invalid_data(String::from("unexpected end of file")));
+        }
+        (0..len).map(|_| self.signed()).collect()
+    }
+
+    fn finish(self) -> io::Result<()> {
+        if self.position == self.bytes.len() {
+            Ok(())
+        } else {
+            Err(invalid_data(String::from(
+                "trailing bytes after the content",
+            )))
+        }
+    }
+}
+
+/// Write a program in the binary format, trailing zeros are not stored.
+pub fn write_binary_program<W: Write>(
+    program: &ProgramMemory,
+    writer: W,
+    with_checksum: bool,
+) -> io::Result<()> {
+    let mut content = Vec::new();
+    push_values(&mut content, program.iter().take(trimmed_len(program)));
+    write_file(writer, KIND_PROGRAM, &content, with_checksum)
+}
+
+/// Read a program written by write_binary_program.
+pub fn read_binary_program<R: Read>(reader: R) -> io::Result<ProgramMemory> {
+    let content = read_file(reader, KIND_PROGRAM)?;
+    let mut decoder = Decoder::new(&content);
+    let values = decoder.values()?;
+    decoder.finish()?;
+    Ok(values.into_iter().collect())
+}
+
+impl Snapshot {
+    /// Write the snapshot in the binary format, see save for the text format.
+    pub fn save_binary<W: Write>(&self, writer: W, with_checksum: bool) -> io::Result<()> {
+        let mut content = Vec::new();
+        push_unsigned(&mut content, self.pc as u64);
+        push_signed(&mut content, self.relative_base as Opcode);
+        content.push(self.halted.into());
+        push_values(&mut content, self.input.iter());
+        push_values(
+            &mut content,
+            self.memory.iter().take(trimmed_len(&self.memory)),
+        );
+        write_file(writer, KIND_SNAPSHOT, &content, with_checksum)
+    }
+
+    /// Read a snapshot written by save_binary.
+    pub fn load_binary<R: Read>(reader: R) -> io::Result<Snapshot> {
+        let content = read_file(reader, KIND_SNAPSHOT)?;
+        let mut decoder = Decoder::new(&content);
+        let pc = decoder.usize()?;
+        let relative_base = decoder.signed()? as isize;
+        let halted = match decoder.byte()? {
+            0 => false,
+            1 => true,
+            other => return Err(invalid_data(format!("invalid halted flag {}", other))),
+        };
+        let input = decoder.values()?;
+        let memory = decoder.values()?.into_iter().collect();
+        decoder.finish()?;
+
+        Ok(Snapshot {
+            memory,
+            pc,
+            relative_base,
+            input,
+            halted,
+        })
+    }
+
+    pub fn save_binary_to_file(&self, filename: &str, with_checksum: bool) -> io::Result<()> {
+        self.save_binary(BufWriter::new(File::create(filename)?), with_checksum)
+    }
+
+    pub fn load_binary_from_file(filename: &str) -> io::Result<Snapshot> {
+        Self::load_binary(BufReader::new(File::open(filename)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_program, IoEvent, Machine};
+
+    #[test]
+    fn test_varints() {
+        let values = [0, 1, -1, 63, -64, 64, 1 << 40, Opcode::MAX, Opcode::MIN];
+        let mut bytes = Vec::new();
+        for &value in values.iter() {
+            push_signed(&mut bytes, value);
+        }
+        // Small values take a single byte.
+        assert_eq!(&bytes[..5], &[0, 2, 1, 126, 127]);
+
+        let mut decoder = Decoder::new(&bytes);
+        for &value in values.iter() {
+            assert_eq!(decoder.signed().unwrap(), value);
+        }
+        decoder.finish().unwrap();
+    }
+
+    #[test]
+    fn test_program_round_trip() {
+        let program = parse_program(include_str!("../../13/input.txt")).unwrap();
+        for &with_checksum in [false, true].iter() {
+            let mut bytes = Vec::new();
+            write_binary_program(&program, &mut bytes, with_checksum).unwrap();
+            let loaded = read_binary_program(bytes.as_slice()).unwrap();
+            assert!(loaded.iter().eq(program.iter()));
+        }
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut machine = Machine::new(parse_program(include_str!("../../13/input.txt")).unwrap());
+        machine.memory_mut()[0] = 2;
+        while machine.run_until_io() != Ok(IoEvent::NeedsInput) {}
+        machine.push_input(-1);
+
+        let mut bytes = Vec::new();
+        machine.snapshot().save_binary(&mut bytes, true).unwrap();
+        let loaded = Snapshot::load_binary(bytes.as_slice()).unwrap();
+
+        assert_eq!(loaded.pc, machine.pc());
+        assert_eq!(loaded.relative_base, machine.relative_base());
+        assert_eq!(loaded.input, vec![-1]);
+        assert!(!loaded.halted);
+        let mut restored = Machine::from_snapshot(loaded);
+        assert_eq!(restored.run_until_io(), machine.run_until_io());
+    }
+
+    #[test]
+    fn test_errors() {
+        let program: ProgramMemory = vec![1, 2, 3].into_iter().collect();
+        let mut bytes = Vec::new();
+        write_binary_program(&program, &mut bytes, true).unwrap();
+
+        // corrupted content
+        let mut corrupted = bytes.clone();
+        corrupted[7] ^= 1;
+        assert!(read_binary_program(corrupted.as_slice()).is_err());
+
+        // truncated file
+        assert!(read_binary_program(&bytes[..bytes.len() - 1]).is_err());
+
+        // a program is not a snapshot
+        assert!(Snapshot::load_binary(bytes.as_slice()).is_err());
+
+        assert!(read_binary_program("1,2,3".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,90 @@
+use std::convert::TryFrom;
+use std::fmt::{Debug, Display};
+
+use num_bigint::BigInt;
+
+use crate::Opcode;
+
+/// The type of a single value in the memory of a Machine.
+/// Opcodes, addresses and relative base adjustments still have to fit into an Opcode,
+/// only the values computed by the program can grow beyond.
+pub trait Cell: Clone + Default + PartialEq + PartialOrd + Debug + Display + From<Opcode> {
+    /// The value as an Opcode, if it fits.
+    fn to_opcode(&self) -> Option<Opcode>;
+
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+    fn wrapping_add(&self, other: &Self) -> Self;
+    fn wrapping_mul(&self, other: &Self) -> Self;
+}
+
+impl Cell for Opcode {
+    fn to_opcode(&self) -> Option<Opcode> {
+        Some(*self)
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Opcode::checked_add(*self, *other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Opcode::checked_mul(*self, *other)
+    }
+
+    fn wrapping_add(&self, other: &Self) -> Self {
+        Opcode::wrapping_add(*self, *other)
+    }
+
+    fn wrapping_mul(&self, other: &Self) -> Self {
+        Opcode::wrapping_mul(*self, *other)
+    }
+}
+
+/// Never overflows, so all arithmetic modes behave the same.
+impl Cell for BigInt {
+    fn to_opcode(&self) -> Option<Opcode> {
+        Opcode::try_from(self).ok()
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+
+    fn wrapping_add(&self, other: &Self) -> Self {
+        self + other
+    }
+
+    fn wrapping_mul(&self, other: &Self) -> Self {
+        self * other
+    }
+}
+
+/// How the add and multiply instructions treat results that do not fit into a cell.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Arithmetic {
+    /// Results wrap around, in debug and release builds alike.
+    #[default]
+    Wrapping,
+    /// Results that do not fit stop the program with IntcodeError::Overflow.
+    Checked,
+}
+
+impl Arithmetic {
+    pub(crate) fn add<C: Cell>(self, a: &C, b: &C) -> Option<C> {
+        match self {
+            Arithmetic::Wrapping => Some(a.wrapping_add(b)),
+            Arithmetic::Checked => a.checked_add(b),
+        }
+    }
+
+    pub(crate) fn mul<C: Cell>(self, a: &C, b: &C) -> Option<C> {
+        match self {
+            Arithmetic::Wrapping => Some(a.wrapping_mul(b)),
+            Arithmetic::Checked => a.checked_mul(b),
+        }
+    }
+}
@@ -0,0 +1,435 @@
+//! Static recovery of the control flow of a program, without running it.
+//!
+//! Starting at address 0, every reachable instruction is decoded and split into basic blocks.
+//! Jumps with an immediate target are followed, jumps reading their target from memory are flagged:
+//! in position mode as indirect jumps, in relative mode as returns of the call/return idiom
+//! that compiled Intcode programs use:
+//!
+//! ```text
+//! ADD #ret, #0 -> [r+0]   ; push the return address
+//! JZ #0, #function        ; call
+//! ret: ...
+//!
+//! function: ARB #4
+//!           ...
+//!           ARB #-4
+//!           JZ #0, [r+0]  ; return
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryInto;
+use std::fmt::Write;
+
+use crate::disasm::{decode_at, format_instruction};
+use crate::{Instruction, Mode, Opcode, ProgramMemory};
+
+/// The way control gets from one block to another.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EdgeKind {
+    /// Execution continues with the next instruction.
+    Fallthrough,
+    /// A jump with an immediate target.
+    Jump,
+    /// A jump to a function, after the return address was written relative to the relative base.
+    Call,
+    /// From a call to the address the function returns to.
+    ReturnSite,
+}
+
+/// How a basic block ends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockExit {
+    /// The last instruction is not a jump, but the next one is the target of another jump.
+    Fallthrough,
+    /// A jump with an immediate target.
+    Jump,
+    Call,
+    /// A jump to an address read in position mode, which cannot be resolved statically.
+    IndirectJump,
+    /// A jump to an address read in relative mode, the return of the call/return idiom.
+    Return,
+    Halt,
+    /// There is no valid instruction at the start of the block, so it has no instructions at all.
+    Invalid,
+}
+
+/// A sequence of instructions that is always executed from start to end.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    pub start: usize,
+    /// The addresses and decoded instructions of the block.
+    pub instructions: Vec<(usize, Instruction)>,
+    pub exit: BlockExit,
+}
+
+impl BasicBlock {
+    /// The address after the last instruction of the block.
+    pub fn end(&self) -> usize {
+        self.instructions
+            .last()
+            .map_or(self.start, |(pc, decoded)| pc + decoded.len)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Edge {
+    /// Start of the block the edge leaves.
+    pub from: usize,
+    /// Start of the block the edge enters.
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+/// What happens after a single instruction.
+struct Successors {
+    exit: Option<BlockExit>,
+    targets: Vec<(usize, EdgeKind)>,
+}
+
+/// The value of a parameter in immediate mode.
+fn immediate(
+    program: &ProgramMemory,
+    pc: usize,
+    decoded: &Instruction,
+    i: usize,
+) -> Option<Opcode> {
+    match decoded.modes[i] {
+        Mode::Immediate => Some(program[pc + 1 + i]),
+        _ => None,
+    }
+}
+
+/// The address constant an instruction writes to the address of the relative base, like `MUL #1, #12 -> [r+0]`.
+/// This is where the caller puts the return address, before the function moves the relative base by ARB.
+fn pushed_address(program: &ProgramMemory, pc: usize, decoded: &Instruction) -> Option<usize> {
+    if decoded.modes[2] != Mode::Relative || program[pc + 3] != 0 {
+        return None;
+    }
+    let a = immediate(program, pc, decoded, 0)?;
+    let b = immediate(program, pc, decoded, 1)?;
+    let value = match (decoded.instruction, a, b) {
+        (1, value, 0) | (1, 0, value) | (2, value, 1) | (2, 1, value) => value,
+        _ => return None,
+    };
+    value
+        .try_into()
+        .ok()
+        .filter(|&address| address < program.len())
+}
+
+/// Determines the successors of the instruction at pc.
+/// return_address is the address pushed in the instructions leading here, see pushed_address.
+fn successors(
+    program: &ProgramMemory,
+    pc: usize,
+    decoded: &Instruction,
+    return_address: Option<usize>,
+) -> Successors {
+    let next = pc + decoded.len;
+    let fallthrough = Successors {
+        exit: None,
+        targets: vec![(next, EdgeKind::Fallthrough)],
+    };
+
+    let jumps_if_nonzero = match decoded.instruction {
+        99 => {
+            return Successors {
+                exit: Some(BlockExit::Halt),
+                targets: Vec::new(),
+            }
+        }
+        5 => true,
+        6 => false,
+        _ => return fallthrough,
+    };
+
+    // A condition in immediate mode makes the jump unconditional (or a no-op).
+    let always = match immediate(program, pc, decoded, 0) {
+        Some(condition) if (condition != 0) == jumps_if_nonzero => true,
+        Some(_) => return fallthrough,
+        None => false,
+    };
+
+    let mut targets = Vec::new();
+    let exit = match decoded.modes[1] {
+        Mode::Immediate => match program[pc + 2].try_into() {
+            Ok(target) => match return_address {
+                Some(return_address) if always => {
+                    targets.push((target, EdgeKind::Call));
+                    targets.push((return_address, EdgeKind::ReturnSite));
+                    BlockExit::Call
+                }
+                _ => {
+                    targets.push((target, EdgeKind::Jump));
+                    BlockExit::Jump
+                }
+            },
+            // a negative target fails at run time, just like an indirect jump to an unknown address
+            Err(_) => BlockExit::IndirectJump,
+        },
+        Mode::Position => BlockExit::IndirectJump,
+        Mode::Relative => BlockExit::Return,
+    };
+    if !always {
+        targets.push((next, EdgeKind::Fallthrough));
+    }
+
+    Successors {
+        exit: Some(exit),
+        targets,
+    }
+}
+
+/// The basic blocks of a program and the edges between them.
+#[derive(Debug, Clone, Default)]
+pub struct ControlFlowGraph {
+    /// All blocks reachable from address 0, by their start address.
+    pub blocks: BTreeMap<usize, BasicBlock>,
+    pub edges: Vec<Edge>,
+    /// Start addresses of all called functions.
+    pub functions: BTreeSet<usize>,
+}
+
+impl ControlFlowGraph {
+    /// Recover the control flow graph of a program, starting at address 0.
+    /// Since only the memory before running is looked at, self-modifying code may not be covered correctly.
+    pub fn build(program: &ProgramMemory) -> Self {
+        let mut instructions = BTreeMap::new();
+        let mut invalid = BTreeSet::new();
+        let mut leaders = BTreeSet::new();
+        // successors of every instruction ending a block
+        let mut exits = BTreeMap::new();
+        let mut functions = BTreeSet::new();
+
+        leaders.insert(0);
+        let mut worklist = vec![(0, None)];
+        while let Some((pc, return_address)) = worklist.pop() {
+            if instructions.contains_key(&pc) || invalid.contains(&pc) {
+                continue;
+            }
+            let decoded = match decode_at(program, pc) {
+                Some(decoded) => decoded,
+                None => {
+                    invalid.insert(pc);
+                    continue;
+                }
+            };
+            instructions.insert(pc, decoded);
+
+            let return_address = pushed_address(program, pc, &decoded).or(return_address);
+            let successors = successors(program, pc, &decoded, return_address);
+            for &(target, kind) in successors.targets.iter() {
+                match kind {
+                    EdgeKind::Fallthrough => worklist.push((target, return_address)),
+                    _ => {
+                        if kind == EdgeKind::Call {
+                            functions.insert(target);
+                        }
+                        leaders.insert(target);
+                        worklist.push((target, None));
+                    }
+                }
+            }
+            if let Some(exit) = successors.exit {
+                exits.insert(pc, (exit, successors.targets));
+                leaders.insert(pc + decoded.len);
+            }
+        }
+
+        let mut graph = ControlFlowGraph {
+            functions,
+            ..Default::default()
+        };
+        let mut current: Option<BasicBlock> = None;
+        for (&pc, &decoded) in instructions.iter() {
+            let mut block = match current.take() {
+                Some(block) if block.end() == pc && !leaders.contains(&pc) => block,
+                previous => {
+                    if let Some(previous) = previous {
+                        graph.finish_block(previous);
+                    }
+                    BasicBlock {
+                        start: pc,
+                        instructions: Vec::new(),
+                        exit: BlockExit::Fallthrough,
+                    }
+                }
+            };
+            block.instructions.push((pc, decoded));
+
+            if let Some((exit, targets)) = exits.remove(&pc) {
+                block.exit = exit;
+                for (to, kind) in targets {
+                    graph.edges.push(Edge {
+                        from: block.start,
+                        to,
+                        kind,
+                    });
+                }
+                graph.blocks.insert(block.start, block);
+            } else {
+                current = Some(block);
+            }
+        }
+        if let Some(block) = current {
+            graph.finish_block(block);
+        }
+
+        for pc in invalid {
+            graph.blocks.insert(
+                pc,
+                BasicBlock {
+                    start: pc,
+                    instructions: Vec::new(),
+                    exit: BlockExit::Invalid,
+                },
+            );
+        }
+        graph
+    }
+
+    /// Adds a block, that ends without a jump, because the next instruction starts another block.
+    /// If the next instruction could not be decoded, that is an invalid block of its own.
+    fn finish_block(&mut self, block: BasicBlock) {
+        self.edges.push(Edge {
+            from: block.start,
+            to: block.end(),
+            kind: EdgeKind::Fallthrough,
+        });
+        self.blocks.insert(block.start, block);
+    }
+
+    /// Addresses of all jump instructions with a target in position mode.
+    pub fn indirect_jumps(&self) -> Vec<usize> {
+        self.blocks
+            .values()
+            .filter(|block| block.exit == BlockExit::IndirectJump)
+            .filter_map(|block| block.instructions.last().map(|&(pc, _)| pc))
+            .collect()
+    }
+
+    /// Render the graph in the Graphviz DOT language, with the instructions of every block as label.
+    /// Functions are drawn with a double border, indirect jumps and invalid blocks in red,
+    /// calls as bold and the edges to the return sites as dashed edges.
+    pub fn to_dot(&self, program: &ProgramMemory) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph cfg {{").unwrap();
+        writeln!(dot, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+
+        for block in self.blocks.values() {
+            let mut label = String::new();
+            for (pc, decoded) in block.instructions.iter() {
+                let line = format_instruction(program, *pc, decoded);
+                write!(label, "{:04}: {}\\l", pc, line).unwrap();
+            }
+            let mut attributes = Vec::new();
+            match block.exit {
+                BlockExit::Invalid => {
+                    label = format!("{:04}: invalid\\l", block.start);
+                    attributes.push("color=red");
+                }
+                BlockExit::IndirectJump => attributes.push("color=red"),
+                _ => (),
+            }
+            if self.functions.contains(&block.start) {
+                attributes.push("peripheries=2");
+            }
+            let label = format!("label=\"{}\"", label);
+            attributes.push(&label);
+            writeln!(dot, "    b{} [{}];", block.start, attributes.join(", ")).unwrap();
+        }
+
+        for edge in self.edges.iter() {
+            let style = match edge.kind {
+                EdgeKind::Fallthrough | EdgeKind::Jump => "",
+                EdgeKind::Call => " [style=bold, label=\"call\"]",
+                EdgeKind::ReturnSite => " [style=dashed]",
+            };
+            writeln!(dot, "    b{} -> b{}{};", edge.from, edge.to, style).unwrap();
+        }
+
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble;
+
+    const CALL: &str = "
+        ADD #ret, #0 -> [r+0]
+        JZ #0, #double
+ret:    OUT [r+2]
+        JNZ [flag], [target]
+        HLT
+double: ARB #4
+        MUL [r-2], #2 -> [r-2]
+        ARB #-4
+        JZ #0, [r+0]
+flag:   DATA 0
+target: DATA 0
+";
+
+    #[test]
+    fn test_build() {
+        let graph = ControlFlowGraph::build(&assemble(CALL).unwrap());
+
+        let exits: Vec<_> = graph
+            .blocks
+            .values()
+            .map(|block| (block.start, block.end(), block.exit))
+            .collect();
+        assert_eq!(
+            exits,
+            vec![
+                (0, 7, BlockExit::Call),
+                (7, 12, BlockExit::IndirectJump),
+                (12, 13, BlockExit::Halt),
+                (13, 24, BlockExit::Return),
+            ]
+        );
+        assert_eq!(
+            graph.edges,
+            vec![
+                Edge {
+                    from: 0,
+                    to: 13,
+                    kind: EdgeKind::Call
+                },
+                Edge {
+                    from: 0,
+                    to: 7,
+                    kind: EdgeKind::ReturnSite
+                },
+                Edge {
+                    from: 7,
+                    to: 12,
+                    kind: EdgeKind::Fallthrough
+                },
+            ]
+        );
+        assert_eq!(
+            graph.functions.iter().cloned().collect::<Vec<_>>(),
+            vec![13]
+        );
+        assert_eq!(graph.indirect_jumps(), vec![9]);
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let program = assemble("loop: JNZ [flag], #loop\nHLT\nflag: DATA 1").unwrap();
+        assert_eq!(
+            ControlFlowGraph::build(&program).to_dot(&program),
+            "digraph cfg {
+    node [shape=box, fontname=\"monospace\"];
+    b0 [label=\"0000: JNZ [4], #0\\l\"];
+    b3 [label=\"0003: HLT\\l\"];
+    b0 -> b0;
+    b0 -> b3;
+}
+"
+        );
+    }
+}
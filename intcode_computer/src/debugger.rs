@@ -0,0 +1,353 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, BufRead, Write};
+
+use crate::disasm::format_instruction;
+use crate::{Instruction, IoEvent, Machine, Opcode, ProgramMemory};
+
+const HELP: &str = "Commands:
+  step [n]            execute n instructions (default 1)
+  continue            run until a breakpoint, a watchpoint, missing input or the end of the program
+  break <pc>          stop before executing the instruction at pc
+  unbreak <pc>        remove a breakpoint
+  watch <adr>         stop after the value at adr changed
+  unwatch <adr>       remove a watchpoint
+  info                list breakpoints and watchpoints
+  pc                  print the program counter and the current instruction
+  rb                  print the relative base
+  mem <from> [to]     print the memory from address from up to (excluding) to, at most 1000 values
+  set <adr> <value>   change the value at adr
+  input <value>...    queue values to be read by the program
+  help                print this message
+  quit                leave the debugger";
+
+/// Addresses at or above this can not be set, unless the limits of the machine allow more memory.
+const MAX_ADDRESS: usize = 1 << 20;
+
+/// Number of values the mem command prints at most.
+const MAX_MEM_RANGE: usize = 1000;
+
+/// Why running the program was interrupted.
+enum Stop {
+    Breakpoint,
+    Watchpoint(usize, Opcode, Opcode),
+    NeedsInput,
+    Halted,
+    Error,
+}
+
+/// Interactive debugger around a Machine, with breakpoints on the program counter and watchpoints on memory.
+pub struct Debugger {
+    machine: Machine,
+    breakpoints: BTreeSet<usize>,
+    // watched addresses and their last known value
+    watchpoints: BTreeMap<usize, Opcode>,
+}
+
+fn parse_arg<T: std::str::FromStr>(arg: Option<&str>, name: &str) -> Result<T, String> {
+    let arg = arg.ok_or_else(|| format!("missing argument <{}>", name))?;
+    arg.parse()
+        .map_err(|_| format!("invalid argument <{}>: '{}'", name, arg))
+}
+
+impl Debugger {
+    pub fn new(program: ProgramMemory) -> Self {
+        Self {
+            machine: Machine::new(program),
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+        }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    /// Fails for addresses outside of the memory the program may use, so that set does not allocate huge amounts of memory.
+    fn check_address(&self, adr: usize) -> Result<usize, String> {
+        let max = self.machine.limits().max_memory.unwrap_or(MAX_ADDRESS);
+        if adr < max {
+            Ok(adr)
+        } else {
+            Err(format!(
+                "address {} is out of memory, the limit is {}",
+                adr, max
+            ))
+        }
+    }
+
+    /// Describes the instruction the program counter currently points to.
+    fn current_instruction(&self) -> String {
+        let pc = self.machine.pc();
+        let memory = self.machine.memory();
+        match Instruction::decode(memory[pc], pc) {
+            Ok(decoded) => format!("{:04}: {}", pc, format_instruction(memory, pc, &decoded)),
+            Err(err) => format!("{:04}: DATA {} ({})", pc, memory[pc], err),
+        }
+    }
+
+    /// Executes a single instruction, reporting output and the reason to stop (if any).
+    fn step<W: Write>(&mut self, output: &mut W) -> io::Result<Option<Stop>> {
+        match self.machine.step() {
+            Ok(None) => (),
+            Ok(Some(IoEvent::Output(val))) => writeln!(output, "output: {}", val)?,
+            Ok(Some(IoEvent::NeedsInput)) => return Ok(Some(Stop::NeedsInput)),
+            Ok(Some(IoEvent::Halted)) => return Ok(Some(Stop::Halted)),
+            Err(err) => {
+                writeln!(output, "error: {}", err)?;
+                return Ok(Some(Stop::Error));
+            }
+        }
+
+        let memory = self.machine.memory();
+        for (&adr, old) in self.watchpoints.iter_mut() {
+            let new = memory[adr];
+            if *old != new {
+                let stop = Stop::Watchpoint(adr, *old, new);
+                *old = new;
+                return Ok(Some(stop));
+            }
+        }
+
+        if self.breakpoints.contains(&self.machine.pc()) {
+            return Ok(Some(Stop::Breakpoint));
+        }
+
+        Ok(None)
+    }
+
+    /// Executes up to count instructions (or without limit), printing where the program stopped.
+    fn run<W: Write>(&mut self, count: Option<usize>, output: &mut W) -> io::Result<()> {
+        let mut executed = 0;
+        let stop = loop {
+            if count == Some(executed) {
+                break None;
+            }
+            if let Some(stop) = self.step(output)? {
+                break Some(stop);
+            }
+            executed += 1;
+        };
+
+        match stop {
+            None => (),
+            Some(Stop::Breakpoint) => writeln!(output, "breakpoint reached")?,
+            Some(Stop::Watchpoint(adr, old, new)) => writeln!(
+                output,
+                "watchpoint: [{}] changed from {} to {}",
+                adr, old, new
+            )?,
+            Some(Stop::NeedsInput) => {
+                writeln!(output, "waiting for input, provide it with `input <value>`")?
+            }
+            Some(Stop::Halted) => writeln!(output, "program halted")?,
+            Some(Stop::Error) => (),
+        }
+        writeln!(output, "{}", self.current_instruction())
+    }
+
+    /// Handles a single command line, returns false if the debugger should quit.
+    fn command<W: Write>(&mut self, line: &str, output: &mut W) -> io::Result<bool> {
+        let mut args = line.split_whitespace();
+        let command = match args.next() {
+            Some(command) => command,
+            None => return Ok(true),
+        };
+        let first = args.next();
+        let second = args.next();
+
+        let result: Result<(), String> = match command {
+            "step" | "s" => match first.map(|n| parse_arg(Some(n), "n")).transpose() {
+                Ok(n) => return self.run(Some(n.unwrap_or(1)), output).map(|_| true),
+                Err(err) => Err(err),
+            },
+            "continue" | "c" => return self.run(None, output).map(|_| true),
+            "break" | "b" => parse_arg(first, "pc").map(|pc| {
+                self.breakpoints.insert(pc);
+            }),
+            "unbreak" => parse_arg(first, "pc").map(|pc| {
+                self.breakpoints.remove(&pc);
+            }),
+            "watch" | "w" => parse_arg(first, "adr").map(|adr| {
+                let value = self.machine.memory()[adr];
+                self.watchpoints.insert(adr, value);
+            }),
+            "unwatch" => parse_arg(first, "adr").map(|adr| {
+                self.watchpoints.remove(&adr);
+            }),
+            "info" => {
+                writeln!(output, "breakpoints: {:?}", self.breakpoints)?;
+                writeln!(
+                    output,
+                    "watchpoints: {:?}",
+                    self.watchpoints.keys().collect::<Vec<_>>()
+                )?;
+                Ok(())
+            }
+            "pc" => {
+                writeln!(output, "pc = {}", self.machine.pc())?;
+                writeln!(output, "{}", self.current_instruction())?;
+                Ok(())
+            }
+            "rb" => {
+                writeln!(output, "relative base = {}", self.machine.relative_base())?;
+                Ok(())
+            }
+            "mem" | "m" => match (parse_arg(first, "from"), second) {
+                (Ok(from), None) => {
+                    writeln!(output, "[{}] = {}", from, self.machine.memory()[from])?;
+                    Ok(())
+                }
+                (Ok(from), to) => match parse_arg::<usize>(to, "to") {
+                    Ok(to) if to < from => Err(format!("<to> {} is before <from> {}", to, from)),
+                    Ok(to) if to - from > MAX_MEM_RANGE => Err(format!(
+                        "can not print more than {} values at once",
+                        MAX_MEM_RANGE
+                    )),
+                    Ok(to) => {
+                        let values: Vec<String> = (from..to)
+                            .map(|adr| self.machine.memory()[adr].to_string())
+                            .collect();
+                        writeln!(output, "[{}..{}] = {}", from, to, values.join(","))?;
+                        Ok(())
+                    }
+                    Err(err) => Err(err),
+                },
+                (Err(err), _) => Err(err),
+            },
+            "set" => match (
+                parse_arg(first, "adr").and_then(|adr| self.check_address(adr)),
+                parse_arg(second, "value"),
+            ) {
+                (Ok(adr), Ok(value)) => {
+                    self.machine.memory_mut()[adr] = value;
+                    // Changing memory by hand should not trigger the watchpoint later on.
+                    if let Some(watched) = self.watchpoints.get_mut(&adr) {
+                        *watched = value;
+                    }
+                    Ok(())
+                }
+                (Err(err), _) | (_, Err(err)) => Err(err),
+            },
+            "input" | "i" => {
+                let values = first
+                    .into_iter()
+                    .chain(second)
+                    .chain(args)
+                    .map(|value| parse_arg(Some(value), "value"))
+                    .collect::<Result<Vec<Opcode>, _>>();
+                values.map(|values| {
+                    for value in values {
+                        self.machine.push_input(value);
+                    }
+                })
+            }
+            "help" | "h" => {
+                writeln!(output, "{}", HELP)?;
+                Ok(())
+            }
+            "quit" | "q" => return Ok(false),
+            unknown => Err(format!("unknown command '{}', try `help`", unknown)),
+        };
+
+        if let Err(err) = result {
+            writeln!(output, "error: {}", err)?;
+        }
+        Ok(true)
+    }
+
+    /// Reads commands line by line from input until `quit` or the end of input.
+    pub fn repl<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        writeln!(output, "{}", self.current_instruction())?;
+        write!(output, "(debug) ")?;
+        output.flush()?;
+
+        for line in input.lines() {
+            if !self.command(&line?, &mut output)? {
+                break;
+            }
+            write!(output, "(debug) ")?;
+            output.flush()?;
+        }
+
+        writeln!(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble;
+
+    fn debug(source: &str, commands: &str) -> String {
+        let mut debugger = Debugger::new(assemble(source).unwrap());
+        let mut output = Vec::new();
+        debugger.repl(commands.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    const COUNTER: &str = "
+loop: ADD [counter], #1 -> [counter]
+      OUT [counter]
+      LT [counter], #3 -> [flag]
+      JNZ [flag], #loop
+      IN -> [counter]
+      HLT
+counter: DATA 0
+flag: DATA 0
+";
+
+    #[test]
+    fn test_breakpoints() {
+        let output = debug(COUNTER, "break 4\nc\nc\nunbreak 4\nc\n");
+        assert_eq!(output.matches("breakpoint reached").count(), 2);
+        assert!(output.contains("output: 3"));
+        assert!(output.contains("waiting for input"));
+        assert!(output.contains("0013: IN -> [16]"));
+    }
+
+    #[test]
+    fn test_watchpoints_and_memory() {
+        let output = debug(
+            COUNTER,
+            "watch 16\nc\nmem 16\nset 16 5\ninput 7\nc\nc\nmem 15 18\n",
+        );
+        assert!(output.contains("watchpoint: [16] changed from 0 to 1"));
+        assert!(output.contains("[16] = 1"));
+        // After setting the counter to 5, it is printed, the loop is left and 7 is read.
+        assert!(output.contains("output: 5"));
+        assert!(output.contains("watchpoint: [16] changed from 5 to 7"));
+        assert!(output.contains("[15..18] = 99,7,0"));
+    }
+
+    #[test]
+    fn test_mem_range() {
+        let output = debug(COUNTER, "mem 5 3\nmem 0 10000000000\nmem 0 1000\n");
+        assert!(output.contains("error: <to> 3 is before <from> 5"));
+        assert!(output.contains("error: can not print more than 1000 values at once"));
+        assert!(output.contains("[0..1000] = 1001,16,1,16,"));
+    }
+
+    #[test]
+    fn test_set_out_of_memory() {
+        let output = debug(
+            COUNTER,
+            "set 1000000000000 1
+mem 1000000000000
+",
+        );
+        assert!(
+            output.contains("error: address 1000000000000 is out of memory, the limit is 1048576")
+        );
+        assert!(output.contains("[1000000000000] = 0"));
+        assert_eq!(
+            debug(
+                COUNTER,
+                "set 1048575 1
+"
+            )
+            .matches("error")
+            .count(),
+            0
+        );
+    }
+}
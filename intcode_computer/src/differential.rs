@@ -0,0 +1,305 @@
+//! Differential testing against the Python implementation (`intcode_computer.py` in the root of the repository).
+//! Both implementations run the same program with the same inputs, afterwards their outputs,
+//! whether they halted and their final memory are compared.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{IoEvent, Machine, Opcode, ProgramMemory};
+
+/// Used to give every run of the Python implementation its own memory file.
+static PYTHON_RUNS: AtomicUsize = AtomicUsize::new(0);
+
+/// Everything observable about a finished run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Run {
+    /// Whether the program halted, it failed otherwise (for example because there was no input left).
+    pub halted: bool,
+    pub output: Vec<Opcode>,
+    pub memory: Vec<Opcode>,
+}
+
+/// The first difference between two runs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Divergence {
+    /// The outputs differ at index, None if one of the implementations wrote less values.
+    Output {
+        index: usize,
+        rust: Option<Opcode>,
+        python: Option<Opcode>,
+    },
+    /// One implementation halted, while the other one failed.
+    Halted { rust: bool, python: bool },
+    /// The final memory differs at address.
+    Memory {
+        address: usize,
+        rust: Opcode,
+        python: Opcode,
+    },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show =
+            |value: &Option<Opcode>| value.map_or(String::from("nothing"), |v| v.to_string());
+        match self {
+            Divergence::Output {
+                index,
+                rust,
+                python,
+            } => write!(
+                f,
+                "output {} differs: rust wrote {}, python wrote {}",
+                index,
+                show(rust),
+                show(python)
+            ),
+            Divergence::Halted { rust, python } => write!(
+                f,
+                "rust {}, but python {}",
+                if *rust { "halted" } else { "failed" },
+                if *python { "halted" } else { "failed" }
+            ),
+            Divergence::Memory {
+                address,
+                rust,
+                python,
+            } => write!(
+                f,
+                "memory at {} differs: rust has {}, python has {}",
+                address, rust, python
+            ),
+        }
+    }
+}
+
+/// Run the program with this crate until it halts, fails or needs more input than given.
+pub fn run_rust(program: &ProgramMemory, inputs: &[Opcode]) -> Run {
+    let mut machine = Machine::new(program.clone());
+    let mut inputs = inputs.iter();
+    let mut output = Vec::new();
+    let halted = loop {
+        match machine.run_until_io() {
+            Ok(IoEvent::NeedsInput) => match inputs.next() {
+                Some(&value) => machine.push_input(value),
+                None => break false,
+            },
+            Ok(IoEvent::Output(value)) => output.push(value),
+            Ok(IoEvent::Halted) => break true,
+            Err(_) => break false,
+        }
+    };
+    Run {
+        halted,
+        output,
+        memory: machine.memory().iter().cloned().collect(),
+    }
+}
+
+fn parse_values(text: &str, separator: char) -> io::Result<Vec<Opcode>> {
+    text.split(separator)
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(|value| {
+            value.parse().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("python produced invalid value '{}'", value),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Run the program file with the Python implementation at script, which is expected to be run by `python3`.
+/// Inputs are passed on stdin, one per line, so the Python implementation fails once they are used up.
+pub fn run_python(script: &str, filename: &str, inputs: &[Opcode]) -> io::Result<Run> {
+    let run = PYTHON_RUNS.fetch_add(1, Ordering::SeqCst);
+    let memory_file =
+        std::env::temp_dir().join(format!("intcode_memory_{}_{}.txt", std::process::id(), run));
+
+    let mut child = Command::new("python3")
+        .arg(script)
+        .arg(filename)
+        .arg("--memory")
+        .arg(&memory_file)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    {
+        let mut stdin = child.stdin.take().unwrap();
+        for value in inputs {
+            // The program may stop before reading all inputs, which closes the pipe.
+            if writeln!(stdin, "{}", value).is_err() {
+                break;
+            }
+        }
+    }
+    let result = child.wait_with_output()?;
+
+    let memory = fs::read_to_string(&memory_file);
+    fs::remove_file(&memory_file).ok();
+    Ok(Run {
+        halted: result.status.success(),
+        output: parse_values(&String::from_utf8_lossy(&result.stdout), '\n')?,
+        memory: parse_values(&memory?, ',')?,
+    })
+}
+
+/// Find the first difference between two runs: in the output, in how they stopped or in the final memory.
+/// Memory beyond the end of one of the runs counts as zero, since both implementations grow it differently.
+pub fn compare(rust: &Run, python: &Run) -> Option<Divergence> {
+    let outputs = rust.output.len().max(python.output.len());
+    for index in 0..outputs {
+        let (r, p) = (rust.output.get(index), python.output.get(index));
+        if r != p {
+            return Some(Divergence::Output {
+                index,
+                rust: r.cloned(),
+                python: p.cloned(),
+            });
+        }
+    }
+
+    if rust.halted != python.halted {
+        return Some(Divergence::Halted {
+            rust: rust.halted,
+            python: python.halted,
+        });
+    }
+
+    let len = rust.memory.len().max(python.memory.len());
+    (0..len).find_map(|address| {
+        let r = rust.memory.get(address).cloned().unwrap_or(0);
+        let p = python.memory.get(address).cloned().unwrap_or(0);
+        if r != p {
+            Some(Divergence::Memory {
+                address,
+                rust: r,
+                python: p,
+            })
+        } else {
+            None
+        }
+    })
+}
+
+/// Run the program file with both implementations and compare the results.
+pub fn compare_with_python(
+    script: &str,
+    filename: &str,
+    inputs: &[Opcode],
+) -> io::Result<Option<Divergence>> {
+    let program = crate::load_program_from_file(filename)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+    let rust = run_rust(&program, inputs);
+    let python = run_python(script, filename, inputs)?;
+    Ok(compare(&rust, &python))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// Inputs for the programs of the days, that need some, every list is a separate run.
+    /// Robots just get a few values, until the program fails because of missing input.
+    fn inputs(day: &str) -> Vec<Vec<Opcode>> {
+        match day {
+            "05" | "intcode_computer" => vec![vec![1], vec![5]],
+            "07" => vec![vec![0, 0], vec![3, 17], vec![9, 0, 1, 2]],
+            "09" => vec![vec![1], vec![2]],
+            "11" => vec![vec![0, 1, 1, 0, 1, 0, 0, 1, 0, 0]],
+            "15" => vec![vec![1, 1, 3, 3, 2, 2, 4, 4, 1, 4]],
+            _ => vec![vec![]],
+        }
+    }
+
+    /// All Intcode programs of the repository: the inputs of all days using Intcode and the samples of day 7 and 9.
+    fn programs(root: &Path) -> Vec<(String, String)> {
+        let mut programs = Vec::new();
+        let mut entries: Vec<_> = fs::read_dir(root)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        entries.sort();
+        for dir in entries {
+            let day = dir.file_name().unwrap().to_string_lossy().into_owned();
+            let mut files = vec![dir.join("input.txt")];
+            if day == "07" || day == "09" {
+                files.extend((2..=4).map(|i| dir.join(format!("in{}.txt", i))));
+            }
+            for file in files {
+                // Only Intcode programs are comma separated numbers.
+                match fs::read_to_string(&file) {
+                    Ok(text) if text.contains(',') && crate::parse_program(&text).is_ok() => {
+                        programs.push((day.clone(), file.to_string_lossy().into_owned()))
+                    }
+                    _ => (),
+                }
+            }
+        }
+        programs
+    }
+
+    #[test]
+    fn test_against_python() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+        let script = root.join("intcode_computer.py");
+        let script = script.to_str().unwrap();
+        if Command::new("python3").arg("--version").output().is_err() {
+            eprintln!("python3 is not available, skipping the differential test");
+            return;
+        }
+
+        let programs = programs(&root);
+        assert!(programs.len() > 10);
+        for (day, filename) in programs {
+            for inputs in inputs(&day) {
+                match compare_with_python(script, &filename, &inputs) {
+                    Ok(None) => (),
+                    Ok(Some(divergence)) => {
+                        panic!("{} with inputs {:?}: {}", filename, inputs, divergence)
+                    }
+                    Err(err) => panic!("{}: {}", filename, err),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let run = |halted, output: &[Opcode], memory: &[Opcode]| Run {
+            halted,
+            output: output.to_vec(),
+            memory: memory.to_vec(),
+        };
+        let rust = run(true, &[1, 2], &[99, 0, 0]);
+        assert_eq!(compare(&rust, &run(true, &[1, 2], &[99])), None);
+        assert_eq!(
+            compare(&rust, &run(true, &[1], &[99])),
+            Some(Divergence::Output {
+                index: 1,
+                rust: Some(2),
+                python: None
+            })
+        );
+        assert_eq!(
+            compare(&rust, &run(false, &[1, 2], &[99])),
+            Some(Divergence::Halted {
+                rust: true,
+                python: false
+            })
+        );
+        assert_eq!(
+            compare(&rust, &run(true, &[1, 2], &[99, 0, 7]))
+                .unwrap()
+                .to_string(),
+            "memory at 2 differs: rust has 0, python has 7"
+        );
+    }
+}
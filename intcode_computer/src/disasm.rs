@@ -0,0 +1,114 @@
+use std::fmt::Write;
+
+use crate::{Instruction, Mode, Opcode, ProgramMemory};
+
+/// Maximum number of values listed in a single line of data.
+pub(crate) const DATA_VALUES_PER_LINE: usize = 8;
+
+/// Formats a single parameter according to its mode:
+/// `[100]` for position mode, `#5` for immediate mode and `[r+3]` for relative mode.
+fn format_parameter(value: Opcode, mode: Mode) -> String {
+    match mode {
+        Mode::Position => format!("[{}]", value),
+        Mode::Immediate => format!("#{}", value),
+        Mode::Relative if value < 0 => format!("[r{}]", value),
+        Mode::Relative => format!("[r+{}]", value),
+    }
+}
+
+/// Formats a decoded instruction found at pc (without the address), for example `ADD [r+3], #5 -> [100]`.
+pub(crate) fn format_instruction(
+    program: &ProgramMemory,
+    pc: usize,
+    decoded: &Instruction,
+) -> String {
+    let write_parameter = decoded.write_parameter();
+    let mut reads = Vec::new();
+    let mut write = None;
+
+    for i in 0..decoded.parameter_count() {
+        let parameter = format_parameter(program[pc + 1 + i], decoded.modes[i]);
+        if Some(i) == write_parameter {
+            write = Some(parameter);
+        } else {
+            reads.push(parameter);
+        }
+    }
+
+    let mut line = String::from(decoded.mnemonic());
+    if !reads.is_empty() {
+        line.push(' ');
+        line.push_str(&reads.join(", "));
+    }
+    if let Some(write) = write {
+        line.push_str(" -> ");
+        line.push_str(&write);
+    }
+    line
+}
+
+/// Decodes the instruction at pc, if there is one that completely fits into the program.
+pub(crate) fn decode_at(program: &ProgramMemory, pc: usize) -> Option<Instruction> {
+    Instruction::decode(program[pc], pc)
+        .ok()
+        .filter(|decoded| pc + decoded.len <= program.len())
+}
+
+/// Produce a readable listing of a program, one instruction per line.
+/// Values that cannot be decoded as an instruction are listed as data.
+/// Since the whole memory is walked front to back, data that happens to be a valid opcode is listed as an instruction.
+pub fn disassemble(program: &ProgramMemory) -> String {
+    let mut listing = String::new();
+    let mut pc = 0;
+
+    while pc < program.len() {
+        match decode_at(program, pc) {
+            Some(decoded) => {
+                let line = format_instruction(program, pc, &decoded);
+                writeln!(listing, "{:04}: {}", pc, line).unwrap();
+                pc += decoded.len;
+            }
+            None => {
+                // Collect the whole region that cannot be decoded.
+                let start = pc;
+                let mut values = Vec::new();
+                while pc < program.len()
+                    && values.len() < DATA_VALUES_PER_LINE
+                    && (pc == start || decode_at(program, pc).is_none())
+                {
+                    values.push(program[pc].to_string());
+                    pc += 1;
+                }
+                writeln!(listing, "{:04}: DATA {}", start, values.join(", ")).unwrap();
+            }
+        }
+    }
+
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disassemble() {
+        let program: ProgramMemory = vec![
+            109, 19, 21201, 3, 5, 100, 1001, -7, 1, 12, 3, 8, 1106, 0, 1, 99, 12345, 67, 4,
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            disassemble(&program),
+            "0000: ARB #19
+0002: ADD [r+3], #5 -> [r+100]
+0006: ADD [-7], #1 -> [12]
+0010: IN -> [8]
+0012: JZ #0, #1
+0015: HLT
+0016: DATA 12345, 67, 4
+"
+        );
+    }
+}
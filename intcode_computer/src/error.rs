@@ -0,0 +1,163 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::Opcode;
+
+/// Everything that can go wrong while executing an Intcode program.
+/// Every variant carries the program counter and, unless the opcode itself is too large, the raw opcode
+/// of the instruction that failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IntcodeError {
+    /// The instruction part of the opcode is not known.
+    InvalidOpcode { pc: usize, opcode: Opcode },
+    /// One of the parameter modes of the opcode is not known.
+    InvalidMode {
+        pc: usize,
+        opcode: Opcode,
+        mode: Opcode,
+    },
+    /// A parameter, a jump target or a relative address resolved to a negative address.
+    NegativeAddress {
+        pc: usize,
+        opcode: Opcode,
+        address: Opcode,
+    },
+    /// The instruction wants to write to a parameter given in immediate mode.
+    ImmediateWrite { pc: usize, opcode: Opcode },
+    /// Reading or writing through the IO failed.
+    Io {
+        pc: usize,
+        opcode: Opcode,
+        message: String,
+    },
+    /// The maximum number of instructions given by the Limits was executed already.
+    InstructionLimit {
+        pc: usize,
+        opcode: Opcode,
+        limit: u64,
+    },
+    /// The instruction wants to write beyond the maximum memory given by the Limits.
+    MemoryLimit {
+        pc: usize,
+        opcode: Opcode,
+        address: usize,
+    },
+    /// The deadline given by the Limits has passed.
+    DeadlineExceeded { pc: usize, opcode: Opcode },
+    /// The result of the instruction does not fit into a cell (with checked Arithmetic),
+    /// or a parameter is too large to be used as an address.
+    Overflow { pc: usize, opcode: Opcode },
+    /// The value at pc is too large to be an opcode, which only happens with cells larger than an Opcode.
+    OpcodeOverflow { pc: usize, value: String },
+}
+
+impl IntcodeError {
+    /// The program counter of the instruction that failed.
+    pub fn pc(&self) -> usize {
+        use IntcodeError::*;
+        match *self {
+            InvalidOpcode { pc, .. }
+            | InvalidMode { pc, .. }
+            | NegativeAddress { pc, .. }
+            | ImmediateWrite { pc, .. }
+            | Io { pc, .. }
+            | InstructionLimit { pc, .. }
+            | MemoryLimit { pc, .. }
+            | DeadlineExceeded { pc, .. }
+            | Overflow { pc, .. }
+            | OpcodeOverflow { pc, .. } => pc,
+        }
+    }
+
+    /// The raw opcode of the instruction that failed, None if it is too large to be an opcode.
+    pub fn opcode(&self) -> Option<Opcode> {
+        use IntcodeError::*;
+        match *self {
+            InvalidOpcode { opcode, .. }
+            | InvalidMode { opcode, .. }
+            | NegativeAddress { opcode, .. }
+            | ImmediateWrite { opcode, .. }
+            | Io { opcode, .. }
+            | InstructionLimit { opcode, .. }
+            | MemoryLimit { opcode, .. }
+            | DeadlineExceeded { opcode, .. }
+            | Overflow { opcode, .. } => Some(opcode),
+            OpcodeOverflow { .. } => None,
+        }
+    }
+
+    /// Whether the program was stopped by one of its Limits, rather than being malformed or failing IO.
+    pub fn is_limit(&self) -> bool {
+        use IntcodeError::*;
+        matches!(
+            self,
+            InstructionLimit { .. } | MemoryLimit { .. } | DeadlineExceeded { .. }
+        )
+    }
+}
+
+impl fmt::Display for IntcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use IntcodeError::*;
+        match self {
+            InvalidOpcode { pc, opcode } => {
+                write!(f, "unsupported instruction {} at pc {}", opcode, pc)
+            }
+            InvalidMode { pc, opcode, mode } => write!(
+                f,
+                "unsupported operand mode {} in opcode {} at pc {}",
+                mode, opcode, pc
+            ),
+            NegativeAddress {
+                pc,
+                opcode,
+                address,
+            } => write!(
+                f,
+                "negative address {} used by opcode {} at pc {}",
+                address, opcode, pc
+            ),
+            ImmediateWrite { pc, opcode } => write!(
+                f,
+                "opcode {} at pc {} writes to a parameter in immediate mode",
+                opcode, pc
+            ),
+            Io {
+                pc,
+                opcode,
+                message,
+            } => write!(
+                f,
+                "IO failed for opcode {} at pc {}: {}",
+                opcode, pc, message
+            ),
+            InstructionLimit { pc, opcode, limit } => write!(
+                f,
+                "instruction limit of {} reached before opcode {} at pc {}",
+                limit, opcode, pc
+            ),
+            MemoryLimit {
+                pc,
+                opcode,
+                address,
+            } => write!(
+                f,
+                "opcode {} at pc {} writes to address {} beyond the memory limit",
+                opcode, pc, address
+            ),
+            DeadlineExceeded { pc, opcode } => {
+                write!(f, "deadline exceeded before opcode {} at pc {}", opcode, pc)
+            }
+            Overflow { pc, opcode } => write!(f, "overflow in opcode {} at pc {}", opcode, pc),
+            OpcodeOverflow { pc, value } => {
+                write!(
+                    f,
+                    "value {} at pc {} is too large to be an opcode",
+                    value, pc
+                )
+            }
+        }
+    }
+}
+
+impl Error for IntcodeError {}
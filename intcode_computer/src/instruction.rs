@@ -0,0 +1,227 @@
+use std::convert::TryInto;
+
+use crate::{IntcodeError, Opcode};
+
+/// Pairs of instruction part of an opcode and the correspondig length (including parameters).
+pub(crate) const OPCODE_LENGHTS_ARR: [(u8, u8); 10] = [
+    (99, 1),
+    (1, 4),
+    (2, 4),
+    (3, 2),
+    (4, 2),
+    (5, 3),
+    (6, 3),
+    (7, 4),
+    (8, 4),
+    (9, 2),
+];
+
+/// Pairs of instruction part of an opcode and the corresponding mnemonic, as used in listings and by the assembler.
+const MNEMONICS_ARR: [(u8, &str); 10] = [
+    (99, "HLT"),
+    (1, "ADD"),
+    (2, "MUL"),
+    (3, "IN"),
+    (4, "OUT"),
+    (5, "JNZ"),
+    (6, "JZ"),
+    (7, "LT"),
+    (8, "EQ"),
+    (9, "ARB"),
+];
+
+/// Lengths from OPCODE_LENGHTS_ARR indexed by the instruction part of the opcode, 0 for unknown instructions.
+/// Since the instruction part is always below 100, this is a fixed table without any lookup cost.
+const OPCODE_LENGTHS_TABLE: [u8; 100] = {
+    let mut table = [0; 100];
+    let mut i = 0;
+    while i < OPCODE_LENGHTS_ARR.len() {
+        let (instruction, len) = OPCODE_LENGHTS_ARR[i];
+        table[instruction as usize] = len;
+        i += 1;
+    }
+    table
+};
+
+/// Looks up the length (including parameters) of an instruction.
+fn opcode_length(instruction: u8) -> Option<usize> {
+    match OPCODE_LENGTHS_TABLE.get(instruction as usize) {
+        Some(&len) if len > 0 => Some(len as usize),
+        _ => None,
+    }
+}
+
+/// Looks up the instruction part of an opcode by its mnemonic, ignoring case.
+pub(crate) fn instruction_for_mnemonic(mnemonic: &str) -> Option<u8> {
+    MNEMONICS_ARR
+        .iter()
+        .find(|&&(_, m)| m.eq_ignore_ascii_case(mnemonic))
+        .map(|&(i, _)| i)
+}
+
+/// The way a parameter of an instruction is interpreted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// The parameter is the address of the value.
+    Position,
+    /// The parameter is the value itself.
+    Immediate,
+    /// The parameter is the address of the value, relative to the relative base.
+    Relative,
+}
+
+/// An opcode split into its instruction and parameter modes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Instruction {
+    pub opcode: Opcode,
+    pub instruction: u8,
+    pub modes: [Mode; 3],
+    /// Length of the instruction, including the parameters.
+    pub len: usize,
+}
+
+impl Instruction {
+    /// Split an opcode found at pc into instruction and modes.
+    /// Fails for unknown instructions and modes as well as for writes in immediate mode.
+    pub fn decode(opcode: Opcode, pc: usize) -> Result<Instruction, IntcodeError> {
+        let invalid_opcode = IntcodeError::InvalidOpcode { pc, opcode };
+        let instruction: u8 = (opcode % 100)
+            .try_into()
+            .map_err(|_| invalid_opcode.clone())?;
+        let len = opcode_length(instruction).ok_or(invalid_opcode)?;
+
+        // Only the modes of the actual parameters are checked, the other digits are ignored.
+        let mut modes = [Mode::Position; 3];
+        let mut mode_digits = opcode / 100;
+        for mode in modes.iter_mut().take(len - 1) {
+            *mode = match mode_digits % 10 {
+                0 => Mode::Position,
+                1 => Mode::Immediate,
+                2 => Mode::Relative,
+                mode => return Err(IntcodeError::InvalidMode { pc, opcode, mode }),
+            };
+            mode_digits /= 10;
+        }
+
+        let decoded = Instruction {
+            opcode,
+            instruction,
+            modes,
+            len,
+        };
+
+        // instructions writing to memory must not get their target parameter in immediate mode
+        if let Some(i) = decoded.write_parameter() {
+            if modes[i] == Mode::Immediate {
+                return Err(IntcodeError::ImmediateWrite { pc, opcode });
+            }
+        }
+
+        Ok(decoded)
+    }
+
+    /// Number of parameters following the opcode.
+    pub fn parameter_count(&self) -> usize {
+        self.len - 1
+    }
+
+    /// Index of the parameter, that the instruction writes to (if it writes at all).
+    pub fn write_parameter(&self) -> Option<usize> {
+        match self.instruction {
+            1 | 2 | 7 | 8 => Some(2),
+            3 => Some(0),
+            _ => None,
+        }
+    }
+
+    /// Short name of the instruction, as used in disassembly listings.
+    pub fn mnemonic(&self) -> &'static str {
+        MNEMONICS_ARR
+            .iter()
+            .find(|&&(i, _)| i == self.instruction)
+            .map(|&(_, m)| m)
+            // decode only accepts instructions from OPCODE_LENGHTS_ARR, which all have a mnemonic
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_decode_modes() {
+        let decoded = Instruction::decode(21002, 7).unwrap();
+        assert_eq!(decoded.instruction, 2);
+        assert_eq!(
+            decoded.modes,
+            [Mode::Position, Mode::Immediate, Mode::Relative]
+        );
+        assert_eq!(decoded.len, 4);
+
+        // digits beyond the parameters are ignored
+        assert_eq!(Instruction::decode(399999, 0).unwrap().len, 1);
+        assert_eq!(
+            Instruction::decode(11101, 3),
+            Err(IntcodeError::ImmediateWrite {
+                pc: 3,
+                opcode: 11101
+            })
+        );
+        assert_eq!(
+            Instruction::decode(3104, 3),
+            Ok(Instruction {
+                opcode: 3104,
+                instruction: 4,
+                modes: [Mode::Immediate, Mode::Position, Mode::Position],
+                len: 2
+            })
+        );
+        assert_eq!(
+            Instruction::decode(305, 3),
+            Err(IntcodeError::InvalidMode {
+                pc: 3,
+                opcode: 305,
+                mode: 3
+            })
+        );
+    }
+
+    proptest! {
+        /// Decoding splits every valid opcode into the digits it was built from.
+        #[test]
+        fn test_decode_roundtrip(
+            index in 0..OPCODE_LENGHTS_ARR.len(),
+            digits in prop::array::uniform3(0..3i64),
+            pc in 0..1000usize,
+        ) {
+            let (instruction, len) = OPCODE_LENGHTS_ARR[index];
+            let opcode = instruction as Opcode + 100 * digits[0] + 1000 * digits[1] + 10000 * digits[2];
+            match Instruction::decode(opcode, pc) {
+                Ok(decoded) => {
+                    prop_assert_eq!(decoded.instruction, instruction);
+                    prop_assert_eq!(decoded.len, len as usize);
+                    for (mode, digit) in decoded.modes.iter().zip(digits.iter()).take(decoded.parameter_count()) {
+                        let expected = [Mode::Position, Mode::Immediate, Mode::Relative][*digit as usize];
+                        prop_assert_eq!(*mode, expected);
+                    }
+                    let write = decoded.write_parameter().map(|i| decoded.modes[i]);
+                    prop_assert_ne!(write, Some(Mode::Immediate));
+                }
+                Err(err) => {
+                    prop_assert_eq!(err, IntcodeError::ImmediateWrite { pc, opcode });
+                }
+            }
+        }
+
+        /// Decoding never panics and fails at the given pc.
+        #[test]
+        fn test_decode_any(opcode in any::<Opcode>(), pc in any::<usize>()) {
+            if let Err(err) = Instruction::decode(opcode, pc) {
+                prop_assert_eq!(err.pc(), pc);
+                prop_assert_eq!(err.opcode(), Some(opcode));
+            }
+        }
+    }
+}
@@ -1,18 +1,82 @@
 //! Implementation of an Intcode Computer as described in the 2019 [Advent of Code](adventofcode.com)
 
-use std::collections::HashMap;
-use std::convert::TryInto;
-use std::fs;
+use std::cell::RefCell;
 use std::io;
 use std::iter::FromIterator;
+use std::mem;
 use std::ops::{Index, IndexMut};
 
+mod error;
+pub use error::IntcodeError;
+
+mod instruction;
+pub use instruction::{Instruction, Mode};
+
+mod cell;
+pub use cell::{Arithmetic, Cell};
+pub use num_bigint::BigInt;
+
+mod limits;
+pub use limits::Limits;
+
+mod memory;
+pub use memory::{Memory, SparseMemory};
+
+mod machine;
+pub use machine::{IoEvent, Machine};
+
+mod self_modification;
+pub use self_modification::{SelfModification, SelfModifications};
+
+pub mod reference;
+
+pub mod differential;
+
+mod snapshot;
+pub use snapshot::Snapshot;
+
+mod binary;
+pub use binary::{read_binary_program, write_binary_program};
+
+mod loader;
+pub use loader::{load_program, load_program_from_file, parse_program, LoadError};
+
+mod adapters;
+pub use adapters::{ChainIo, FnIo, TeeIo, VecIo, NO_INPUT_LEFT};
+
+mod ascii;
+pub use ascii::{AsciiEvent, AsciiIo};
+
+mod disasm;
+pub use disasm::disassemble;
+
+mod cfg;
+pub use cfg::{BasicBlock, BlockExit, ControlFlowGraph, Edge, EdgeKind};
+
+pub mod assembler;
+pub use assembler::assemble;
+
+mod debugger;
+pub use debugger::Debugger;
+
+mod trace;
+pub use trace::{LineTracer, RingBufferTracer, TraceRecord, Tracer};
+
+mod profile;
+pub use profile::{HotLoop, Profiler};
+
+mod session;
+pub use session::{Recorder, Replayer, SessionEvent};
+
+#[cfg(test)]
+mod properties;
+
 pub type Opcode = i64;
 
 /// Factor of growth of the underlying vector in ProgramMemory.
 const MEMORY_MULTIPLIER: usize = 2;
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Debug)]
 pub struct InfiniteVector<T: Clone + Default> {
     data: Vec<T>,
     default: T,
@@ -41,7 +105,11 @@ impl<T: Clone + Default> InfiniteVector<T> {
         self.data.len()
     }
 
-    pub fn iter(&self) -> core::slice::Iter<T> {
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn iter(&self) -> core::slice::Iter<'_, T> {
         self.data.iter()
     }
 }
@@ -79,10 +147,75 @@ impl<T: Clone + Default> IndexMut<usize> for InfiniteVector<T> {
 /// Provides a vector without an upper index bound.
 pub type ProgramMemory = InfiniteVector<Opcode>;
 
+/// Memory with arbitrary-precision cells, for programs computing numbers that do not fit into an Opcode.
+pub type BigProgramMemory = InfiniteVector<BigInt>;
+
 /// IO of the Computer can be done through any struct that implements this.
+/// The fallible methods are used by try_run_program and Machine::run and can be overwritten to report IO failures.
+/// For IO with mutable state, see IntcodeIoMut.
 pub trait IntcodeIo {
     fn read(&self) -> Opcode;
     fn write(&self, value: &Opcode);
+
+    fn try_read(&self) -> Result<Opcode, String> {
+        Ok(self.read())
+    }
+
+    fn try_write(&self, value: &Opcode) -> Result<(), String> {
+        self.write(value);
+        Ok(())
+    }
+}
+
+/// IO of the Computer with mutable access to the state of the implementor, so that no RefCell is needed.
+/// Both methods are fallible, a failure stops the program with IntcodeError::Io.
+///
+/// run_program and Machine::run take anything implementing this by value, which bridges the two IO traits:
+/// a shared reference to an IntcodeIo is an IntcodeIoMut and a RefCell around an IntcodeIoMut is an IntcodeIo.
+/// The values are Opcodes, unless the memory of the machine uses another Cell type.
+pub trait IntcodeIoMut<C = Opcode> {
+    fn input(&mut self) -> Result<C, String>;
+    fn output(&mut self, value: C) -> Result<(), String>;
+}
+
+impl<T: IntcodeIo + ?Sized> IntcodeIoMut for &T {
+    fn input(&mut self) -> Result<Opcode, String> {
+        self.try_read()
+    }
+
+    fn output(&mut self, value: Opcode) -> Result<(), String> {
+        self.try_write(&value)
+    }
+}
+
+impl<C, T: IntcodeIoMut<C> + ?Sized> IntcodeIoMut<C> for &mut T {
+    fn input(&mut self) -> Result<C, String> {
+        (**self).input()
+    }
+
+    fn output(&mut self, value: C) -> Result<(), String> {
+        (**self).output(value)
+    }
+}
+
+/// Panics in read and write if the inner IO fails, like any IntcodeIo without fallible methods.
+impl<T: IntcodeIoMut> IntcodeIo for RefCell<T> {
+    fn read(&self) -> Opcode {
+        self.try_read().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    fn write(&self, value: &Opcode) {
+        self.try_write(value)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    fn try_read(&self) -> Result<Opcode, String> {
+        self.borrow_mut().input()
+    }
+
+    fn try_write(&self, value: &Opcode) -> Result<(), String> {
+        self.borrow_mut().output(*value)
+    }
 }
 
 /// Implementation of IntcodeIo using stdin and stdout (println! macro).
@@ -98,9 +231,19 @@ impl IntcodeStdIo {
 
 impl IntcodeIo for IntcodeStdIo {
     fn read(&self) -> Opcode {
+        self.try_read().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    fn try_read(&self) -> Result<Opcode, String> {
         let mut val = String::new();
-        self.stdin.read_line(&mut val).unwrap();
-        val.trim().parse().unwrap()
+        match self.stdin.read_line(&mut val) {
+            Ok(0) => return Err(String::from("stdin is closed")),
+            Ok(_) => (),
+            Err(err) => return Err(err.to_string()),
+        }
+        val.trim()
+            .parse()
+            .map_err(|err| format!("invalid input '{}': {}", val.trim(), err))
     }
 
     fn write(&self, value: &Opcode) {
@@ -108,135 +251,68 @@ impl IntcodeIo for IntcodeStdIo {
     }
 }
 
-/// Pairs of instruction part of an opcode and the correspondig length (including parameters).
-/// This array can (and will) be used to initialize a HashMap<u8, u8> for faster lookup.
-const OPCODE_LENGHTS_ARR: [(u8, u8); 10] = [
-    (99, 1),
-    (1, 4),
-    (2, 4),
-    (3, 2),
-    (4, 2),
-    (5, 3),
-    (6, 3),
-    (7, 4),
-    (8, 4),
-    (9, 2),
-];
-
 /// Run a program as described in the challenges of [Advent of Code](adventofcode.com).
-pub fn run_program<T: IntcodeIo>(program: &mut ProgramMemory, inout: &T) {
-    let opcode_lenghts: HashMap<u8, u8> = OPCODE_LENGHTS_ARR.iter().cloned().collect();
-
-    // program counter, starting at index 0
-    let mut pc = 0usize;
-
-    // relative base, starting at index 0
-    let mut relative_base = 0isize;
-
-    loop {
-        // This covers the access of the opcode and all parameters (without derefferencing these).
-        program.ensure_size(pc + *opcode_lenghts.values().max().unwrap_or(&0) as usize);
-
-        // fetch the opcode and split it into instruction and modes
-        let opcode = program[pc];
-        let instruction: u8 = (opcode % 100).try_into().unwrap();
-        let modes = [
-            (opcode / 100) % 10,
-            (opcode / 1000) % 10,
-            (opcode / 10000) % 10,
-        ];
-
-        let op_len = opcode_lenghts[&instruction] as usize;
-
-        // determine parameter addresses according to parameter modes
-        // the needed values are right after the opcode
-        let parameter_adrs: Vec<usize> = (0usize..(op_len - 1))
-            .map(|i| match modes[i] {
-                // position mode
-                0 => program[pc + 1usize + i as usize] as usize,
-                // immediate mode
-                1 => pc + 1usize + i as usize,
-                // relative mode
-                2 => (relative_base + program[pc + 1usize + i] as isize) as usize,
-                x => panic!("unsupported operand mode: {}", x),
-            })
-            .collect();
-
-        // since we will be accessing these memory addresses, we will have to ensure that they are loaded too
-        let max_index = *parameter_adrs.iter().max().unwrap_or(&0);
-        program.ensure_size(max_index);
+/// Panics if the program is malformed, see try_run_program for a fallible version.
+pub fn run_program<M: Memory, T: IntcodeIoMut<M::Cell>>(program: &mut M, inout: T) {
+    if let Err(err) = try_run_program(program, inout) {
+        panic!("{}", err);
+    }
+}
 
-        let params: Vec<&Opcode> = parameter_adrs
-            .iter()
-            .map(|&adr| program.index(adr))
-            .collect();
+/// Run a program like run_program, but return an error instead of panicking if the program is malformed.
+pub fn try_run_program<M: Memory, T: IntcodeIoMut<M::Cell>>(
+    program: &mut M,
+    inout: T,
+) -> Result<(), IntcodeError> {
+    try_run_program_with_limits(program, inout, Limits::none())
+}
 
-        // set if a jump is performed; if not the pc will have to be incremented according to the opcode length and parameter count
-        let mut pc_jumped = false;
+/// Run a program like try_run_program, but stop with an error once one of the limits is exceeded.
+pub fn try_run_program_with_limits<M: Memory, T: IntcodeIoMut<M::Cell>>(
+    program: &mut M,
+    inout: T,
+    limits: Limits,
+) -> Result<(), IntcodeError> {
+    let mut machine = Machine::new(mem::take(program));
+    machine.set_limits(limits);
+    let result = machine.run(inout);
+    *program = machine.into_memory();
+    result
+}
 
-        match instruction {
-            // halt the program
-            99 => break,
-            // addition
-            1 => {
-                program[parameter_adrs[2]] = *params[0] + *params[1];
-            }
-            // multiplication
-            2 => {
-                program[parameter_adrs[2]] = *params[0] * *params[1];
-            }
-            // input
-            3 => {
-                let val = inout.read();
-                program[parameter_adrs[0]] = val;
-            }
-            // output
-            4 => {
-                inout.write(params[0]);
-            }
-            // jump not equal
-            5 => {
-                if *params[0] != 0 {
-                    pc_jumped = true;
-                    pc = (*params[1]).try_into().unwrap();
-                }
-            }
-            // jump equal
-            6 => {
-                if *params[0] == 0 {
-                    pc_jumped = true;
-                    pc = (*params[1]).try_into().unwrap();
-                }
-            }
-            // less than
-            7 => {
-                program[parameter_adrs[2]] = (params[0] < params[1]).into();
-            }
-            // equality
-            8 => {
-                program[parameter_adrs[2]] = (params[0] == params[1]).into();
-            }
-            // adjust relative base
-            9 => {
-                relative_base += *params[0] as isize;
-            }
-            _ => panic!("unsupported instruction!"),
-        };
+/// Run a program like try_run_program, but stop with IntcodeError::Overflow if an addition or multiplication overflows.
+pub fn try_run_program_checked<M: Memory, T: IntcodeIoMut<M::Cell>>(
+    program: &mut M,
+    inout: T,
+) -> Result<(), IntcodeError> {
+    let mut machine = Machine::new(mem::take(program));
+    machine.set_arithmetic(Arithmetic::Checked);
+    let result = machine.run(inout);
+    *program = machine.into_memory();
+    result
+}
 
-        // pc should not be incremented if the current instruction triggered a jump
-        if !pc_jumped {
-            pc += op_len;
-        }
+/// Run a program like run_program and pass a record of every executed instruction to the tracer.
+pub fn run_program_traced<M: Memory, T: IntcodeIoMut<M::Cell>>(
+    program: &mut M,
+    inout: T,
+    tracer: &mut dyn Tracer<M::Cell>,
+) {
+    let mut machine = Machine::new(mem::take(program));
+    let result = machine.run_traced(inout, tracer);
+    *program = machine.into_memory();
+    if let Err(err) = result {
+        panic!("{}", err);
     }
 }
 
-/// read and parse an intcode program file
+/// read and parse an intcode program file, panicking if that fails (see load_program_from_file for a fallible version)
 pub fn read_program_from_file(filename: &str) -> ProgramMemory {
-    fs::read_to_string(filename)
-        .expect(&format!("File not found: '{}'", filename))
-        .split(",")
-        .map(|s| s.trim().parse::<Opcode>().unwrap())
-        .collect()
+    load_program_from_file(filename).unwrap_or_else(|err| match err {
+        // the message names the file already
+        LoadError::Io(_) => panic!("{}", err),
+        LoadError::Syntax { .. } => panic!("{}: {}", filename, err),
+    })
 }
 
 /// read, parse and execute an intcode program file
@@ -246,3 +322,259 @@ pub fn run_program_from_file(filename: &str) {
     let inout = IntcodeStdIo::new(stdin);
     run_program(&mut program, &inout);
 }
+
+/// read, parse and execute an intcode program file, warning about every write to executed code on stderr
+/// and printing a summary of these writes to stderr once the program halts
+pub fn run_program_from_file_with_self_modifications(filename: &str) {
+    let stdin = io::stdin();
+    let inout = IntcodeStdIo::new(stdin);
+    let mut machine = Machine::new(read_program_from_file(filename));
+    machine.track_self_modifications(true);
+    let result = machine.run(&inout);
+    if let Some(self_modifications) = machine.self_modifications() {
+        eprint!("{}", self_modifications);
+    }
+    if let Err(err) = result {
+        panic!("{}", err);
+    }
+}
+
+/// read, parse and execute an intcode program file, writing a trace of all executed instructions to trace_filename
+pub fn trace_program_from_file(filename: &str, trace_filename: &str) {
+    let mut program = read_program_from_file(filename);
+    let stdin = io::stdin();
+    let inout = IntcodeStdIo::new(stdin);
+    let mut tracer = LineTracer::create(trace_filename)
+        .unwrap_or_else(|err| panic!("Could not create '{}': {}", trace_filename, err));
+    run_program_traced(&mut program, &inout, &mut tracer);
+}
+
+/// read, parse and execute an intcode program file, writing the report and annotated disassembly of a Profiler to report_filename
+pub fn profile_program_from_file(filename: &str, report_filename: &str) {
+    let program = read_program_from_file(filename);
+    let stdin = io::stdin();
+    let inout = IntcodeStdIo::new(stdin);
+    let mut profiler = Profiler::new();
+    run_program_traced(&mut program.clone(), &inout, &mut profiler);
+
+    std::fs::write(report_filename, profiler.full_report(&program))
+        .unwrap_or_else(|err| panic!("Could not write '{}': {}", report_filename, err));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// IO that must not be used by the program under test.
+    struct NoIo;
+
+    impl IntcodeIo for NoIo {
+        fn read(&self) -> Opcode {
+            panic!("program tried to read");
+        }
+
+        fn write(&self, _value: &Opcode) {
+            panic!("program tried to write");
+        }
+    }
+
+    fn run(program: &[Opcode]) -> Result<ProgramMemory, IntcodeError> {
+        let mut program: ProgramMemory = program.iter().cloned().collect();
+        try_run_program(&mut program, &NoIo)?;
+        Ok(program)
+    }
+
+    #[test]
+    fn test_runs_valid_program() {
+        let program = run(&[1101, 100, -1, 4, 0]).unwrap();
+        assert_eq!(program[4], 99);
+    }
+
+    #[test]
+    fn test_invalid_opcode() {
+        assert_eq!(
+            run(&[1101, 1, 41, 4, 99]).err(),
+            Some(IntcodeError::InvalidOpcode { pc: 4, opcode: 42 })
+        );
+        assert_eq!(
+            run(&[-1]).err(),
+            Some(IntcodeError::InvalidOpcode { pc: 0, opcode: -1 })
+        );
+    }
+
+    #[test]
+    fn test_invalid_mode() {
+        assert_eq!(
+            run(&[301, 0, 0, 0, 99]).err(),
+            Some(IntcodeError::InvalidMode {
+                pc: 0,
+                opcode: 301,
+                mode: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_negative_address() {
+        assert_eq!(
+            run(&[1, -3, 0, 0, 99]).err(),
+            Some(IntcodeError::NegativeAddress {
+                pc: 0,
+                opcode: 1,
+                address: -3
+            })
+        );
+        assert_eq!(
+            run(&[1105, 1, -7]).err(),
+            Some(IntcodeError::NegativeAddress {
+                pc: 0,
+                opcode: 1105,
+                address: -7
+            })
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        // An endless loop
+        let mut program: ProgramMemory = vec![1105, 1, 0].into_iter().collect();
+        let limits = Limits {
+            max_instructions: Some(1000),
+            ..Limits::none()
+        };
+        let err = try_run_program_with_limits(&mut program, &NoIo, limits).unwrap_err();
+        assert_eq!(
+            err,
+            IntcodeError::InstructionLimit {
+                pc: 0,
+                opcode: 1105,
+                limit: 1000
+            }
+        );
+        assert!(err.is_limit());
+
+        let limits = Limits {
+            deadline: Some(std::time::Instant::now()),
+            ..Limits::none()
+        };
+        assert_eq!(
+            try_run_program_with_limits(&mut program, &NoIo, limits).err(),
+            Some(IntcodeError::DeadlineExceeded {
+                pc: 0,
+                opcode: 1105
+            })
+        );
+
+        let mut program: ProgramMemory = vec![1101, 1, 2, 1_000_000_000_000, 99]
+            .into_iter()
+            .collect();
+        let limits = Limits {
+            max_memory: Some(1 << 20),
+            ..Limits::none()
+        };
+        assert_eq!(
+            try_run_program_with_limits(&mut program, &NoIo, limits).err(),
+            Some(IntcodeError::MemoryLimit {
+                pc: 0,
+                opcode: 1101,
+                address: 1_000_000_000_000
+            })
+        );
+        assert_eq!(program.len(), 5);
+    }
+
+    /// Provides the queued inputs and collects the outputs, without any interior mutability.
+    struct QueueIo {
+        inputs: Vec<Opcode>,
+        outputs: Vec<Opcode>,
+    }
+
+    impl IntcodeIoMut for QueueIo {
+        fn input(&mut self) -> Result<Opcode, String> {
+            self.inputs
+                .pop()
+                .ok_or_else(|| String::from("out of input"))
+        }
+
+        fn output(&mut self, value: Opcode) -> Result<(), String> {
+            self.outputs.push(value);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_io_mut() {
+        // Reads a value and outputs it doubled, twice.
+        let doubler = [3, 11, 102, 2, 11, 11, 4, 11, 1105, 1, 0, 0];
+        let mut io = QueueIo {
+            inputs: vec![4, 3],
+            outputs: Vec::new(),
+        };
+        let mut program: ProgramMemory = doubler.iter().cloned().collect();
+        assert_eq!(
+            try_run_program(&mut program, &mut io).err(),
+            Some(IntcodeError::Io {
+                pc: 0,
+                opcode: 3,
+                message: String::from("out of input")
+            })
+        );
+        assert_eq!(io.outputs, vec![6, 8]);
+
+        // The same through the old trait.
+        let io = RefCell::new(QueueIo {
+            inputs: vec![5],
+            outputs: Vec::new(),
+        });
+        let mut program: ProgramMemory = doubler.iter().cloned().collect();
+        assert!(try_run_program(&mut program, &io).is_err());
+        assert_eq!(io.into_inner().outputs, vec![10]);
+    }
+
+    #[test]
+    fn test_immediate_write() {
+        assert_eq!(
+            run(&[11101, 1, 1, 0, 99]).err(),
+            Some(IntcodeError::ImmediateWrite {
+                pc: 0,
+                opcode: 11101
+            })
+        );
+    }
+
+    #[test]
+    fn test_infinite_vector() {
+        let mut vector: ProgramMemory = vec![1, 2].into_iter().collect();
+        assert_eq!(vector[1], 2);
+        // reading beyond the end neither fails nor grows the vector
+        assert_eq!(vector[100], 0);
+        assert_eq!(vector.len(), 2);
+
+        vector[10] = 7;
+        assert!(vector.len() > 10);
+        assert_eq!(vector[10], 7);
+        assert_eq!(vector[9], 0);
+        assert_eq!(vector[0], 1);
+    }
+
+    proptest::proptest! {
+        /// InfiniteVector behaves like a map from index to value, where missing values are zero.
+        #[test]
+        fn test_infinite_vector_model(
+            initial in proptest::collection::vec(-5..5i64, 0..20),
+            writes in proptest::collection::vec((0..200usize, -5..5i64), 0..50),
+        ) {
+            let mut vector: ProgramMemory = initial.iter().cloned().collect();
+            let mut model: std::collections::HashMap<usize, Opcode> =
+                initial.into_iter().enumerate().collect();
+            for (index, value) in writes {
+                vector[index] = value;
+                model.insert(index, value);
+            }
+            for index in 0..250 {
+                proptest::prop_assert_eq!(vector[index], model.get(&index).cloned().unwrap_or(0));
+            }
+            proptest::prop_assert!(vector.iter().count() == vector.len());
+        }
+    }
+}
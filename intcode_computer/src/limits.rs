@@ -0,0 +1,21 @@
+use std::time::Instant;
+
+/// Budget for running a program, every limit is optional and unlimited by default.
+/// Exceeding a limit stops the Machine with an IntcodeError before the offending instruction is executed,
+/// so a machine can be continued after raising its limits.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Limits {
+    /// Maximum number of instructions to execute.
+    pub max_instructions: Option<u64>,
+    /// Number of memory cells the program may use: addresses at or above may not be written to.
+    pub max_memory: Option<usize>,
+    /// Point in time after which no further instruction is executed.
+    pub deadline: Option<Instant>,
+}
+
+impl Limits {
+    /// No limits at all, the same as Limits::default().
+    pub fn none() -> Self {
+        Self::default()
+    }
+}
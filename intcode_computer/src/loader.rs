@@ -0,0 +1,162 @@
+//! Parsing of programs in the comma separated format of the puzzle inputs.
+//!
+//! Values may be separated by commas, whitespace and newlines, a single trailing comma is accepted.
+//! Everything after a `#` is a comment:
+//!
+//! ```text
+//! # Outputs its input doubled.
+//! 3,7,       # IN -> [7]
+//! 102,2,7,7, # MUL #2, [7] -> [7]
+//! 4,7,99,0
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+
+use crate::{Opcode, ProgramMemory};
+
+/// Why a program could not be loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadError {
+    /// The source could not be read at all.
+    Io(String),
+    /// The source is malformed at the given (1-based) line and column.
+    Syntax {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(message) => write!(f, "{}", message),
+            LoadError::Syntax {
+                line,
+                column,
+                message,
+            } => write!(f, "line {}, column {}: {}", line, column, message),
+        }
+    }
+}
+
+impl Error for LoadError {}
+
+/// Parse a program from its source.
+pub fn parse_program(source: &str) -> Result<ProgramMemory, LoadError> {
+    let mut values: Vec<Opcode> = Vec::new();
+    // whether a value was read since the last comma
+    let mut after_value = false;
+
+    for (i, line) in source.lines().enumerate() {
+        let code = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+        let error = |start: usize, message: String| LoadError::Syntax {
+            line: i + 1,
+            column: code[..start].chars().count() + 1,
+            message,
+        };
+
+        let mut chars = code.char_indices().peekable();
+        while let Some(&(start, c)) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+            } else if c == ',' {
+                if !after_value {
+                    return Err(error(start, String::from("missing value before ','")));
+                }
+                after_value = false;
+                chars.next();
+            } else {
+                let mut end = code.len();
+                while let Some(&(j, c)) = chars.peek() {
+                    if c == ',' || c.is_whitespace() {
+                        end = j;
+                        break;
+                    }
+                    chars.next();
+                }
+                let token = &code[start..end];
+                let value = token
+                    .parse()
+                    .map_err(|_| error(start, format!("invalid value '{}'", token)))?;
+                values.push(value);
+                after_value = true;
+            }
+        }
+    }
+
+    Ok(values.into_iter().collect())
+}
+
+/// Read and parse a program from any reader.
+pub fn load_program<R: Read>(mut reader: R) -> Result<ProgramMemory, LoadError> {
+    let mut source = String::new();
+    reader
+        .read_to_string(&mut source)
+        .map_err(|err| LoadError::Io(err.to_string()))?;
+    parse_program(&source)
+}
+
+/// Read and parse a program file, IO errors name the file.
+pub fn load_program_from_file(filename: &str) -> Result<ProgramMemory, LoadError> {
+    let file = File::open(filename)
+        .map_err(|err| LoadError::Io(format!("could not open '{}': {}", filename, err)))?;
+    load_program(file).map_err(|err| match err {
+        LoadError::Io(message) => {
+            LoadError::Io(format!("could not read '{}': {}", filename, message))
+        }
+        err => err,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(source: &str) -> Vec<Opcode> {
+        parse_program(source).unwrap().iter().cloned().collect()
+    }
+
+    fn syntax_error(source: &str) -> (usize, usize) {
+        match parse_program(source) {
+            Err(LoadError::Syntax { line, column, .. }) => (line, column),
+            result => panic!("expected a syntax error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_parse_program() {
+        assert_eq!(values("1,0,0,0,99\n"), vec![1, 0, 0, 0, 99]);
+        assert_eq!(values("1, -2,\n 3 4,"), vec![1, -2, 3, 4]);
+        assert_eq!(
+            values("# header\n3,7, # IN -> [7]\n\n4,7,99,0 # end"),
+            vec![3, 7, 4, 7, 99, 0]
+        );
+        assert_eq!(values(""), vec![]);
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        assert_eq!(syntax_error("1,,2"), (1, 3));
+        assert_eq!(syntax_error("1,2\n  3,x4"), (2, 5));
+        assert_eq!(syntax_error(",1"), (1, 1));
+        assert_eq!(syntax_error("1,2,\n,"), (2, 1));
+    }
+
+    #[test]
+    fn test_load_program() {
+        let program = load_program("1101,1,1,0,99".as_bytes()).unwrap();
+        assert_eq!(program[0], 1101);
+
+        match load_program_from_file("does/not/exist.txt") {
+            Err(LoadError::Io(message)) => assert!(message.contains("does/not/exist.txt")),
+            result => panic!("expected an IO error, got {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,663 @@
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::time::Instant;
+
+use crate::{
+    Arithmetic, Cell, Instruction, IntcodeError, IntcodeIoMut, Limits, Memory, Mode, Opcode,
+    ProgramMemory, SelfModifications, Snapshot, TraceRecord, Tracer,
+};
+
+/// Reasons for a machine to stop running and hand control back to its host.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IoEvent<C = Opcode> {
+    /// The program wants to read, but no input is queued.
+    /// The input instruction is executed once a value is provided with Machine::push_input.
+    NeedsInput,
+    /// The program has written a value.
+    Output(C),
+    /// The program has executed the halt instruction.
+    Halted,
+}
+
+/// Resolves the address a raw value refers to, failing for negative addresses.
+fn to_address(value: Opcode, pc: usize, opcode: Opcode) -> Result<usize, IntcodeError> {
+    value.try_into().map_err(|_| IntcodeError::NegativeAddress {
+        pc,
+        opcode,
+        address: value,
+    })
+}
+
+/// Converts a cell to an Opcode, failing for cells that are too large.
+fn to_opcode<C: Cell>(value: &C, pc: usize, opcode: Opcode) -> Result<Opcode, IntcodeError> {
+    value
+        .to_opcode()
+        .ok_or(IntcodeError::Overflow { pc, opcode })
+}
+
+/// The deadline of the Limits is only checked every so many instructions, since reading the clock is slow.
+const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+
+/// Instructions at or above this address are not cached, so that the cache stays small for code at huge addresses.
+const DECODE_CACHE_LIMIT: usize = 1 << 16;
+
+/// An Intcode Computer that can be run instruction by instruction.
+/// In contrast to run_program, the state of the computer is kept between calls,
+/// so that the host can interleave running the program with its own logic.
+/// The memory backend defaults to the dense ProgramMemory, see SparseMemory for programs using huge addresses.
+#[derive(Clone)]
+pub struct Machine<M: Memory = ProgramMemory> {
+    memory: M,
+    // program counter, starting at index 0
+    pc: usize,
+    // relative base, starting at index 0
+    relative_base: isize,
+    input: VecDeque<M::Cell>,
+    halted: bool,
+    // decoded instructions indexed by their address, if caching is enabled
+    decode_cache: Option<Vec<Option<Instruction>>>,
+    limits: Limits,
+    // number of instructions executed so far
+    executed: u64,
+    arithmetic: Arithmetic,
+    // executed code and writes to it, if tracking is enabled
+    self_modifications: Option<SelfModifications<M::Cell>>,
+}
+
+impl<M: Memory> Machine<M> {
+    pub fn new(memory: M) -> Self {
+        Self {
+            memory,
+            pc: 0,
+            relative_base: 0,
+            input: VecDeque::new(),
+            halted: false,
+            decode_cache: None,
+            limits: Limits::default(),
+            executed: 0,
+            arithmetic: Arithmetic::default(),
+            self_modifications: None,
+        }
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn relative_base(&self) -> isize {
+        self.relative_base
+    }
+
+    pub fn memory(&self) -> &M {
+        &self.memory
+    }
+
+    /// Gives out the memory for arbitrary changes, which drops all cached instructions.
+    pub fn memory_mut(&mut self) -> &mut M {
+        self.clear_decode_cache();
+        &mut self.memory
+    }
+
+    pub fn into_memory(self) -> M {
+        self.memory
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Capture the complete state of the machine, including queued input.
+    pub fn snapshot(&self) -> Snapshot<M> {
+        Snapshot {
+            memory: self.memory.clone(),
+            pc: self.pc,
+            relative_base: self.relative_base,
+            input: self.input.iter().cloned().collect(),
+            halted: self.halted,
+        }
+    }
+
+    /// Reset the machine to a previously captured state.
+    /// The decode cache stays enabled (if it was), but is cleared.
+    /// The limits and the number of executed instructions are kept, restoring does not refund the budget.
+    pub fn restore(&mut self, snapshot: &Snapshot<M>) {
+        let snapshot = snapshot.clone();
+        self.memory = snapshot.memory;
+        self.pc = snapshot.pc;
+        self.relative_base = snapshot.relative_base;
+        self.input = snapshot.input.into_iter().collect();
+        self.halted = snapshot.halted;
+        self.clear_decode_cache();
+    }
+
+    pub fn from_snapshot(snapshot: Snapshot<M>) -> Self {
+        Self {
+            memory: snapshot.memory,
+            pc: snapshot.pc,
+            relative_base: snapshot.relative_base,
+            input: snapshot.input.into_iter().collect(),
+            halted: snapshot.halted,
+            decode_cache: None,
+            limits: Limits::default(),
+            executed: 0,
+            arithmetic: Arithmetic::default(),
+            self_modifications: None,
+        }
+    }
+
+    /// Keep decoded instructions around, instead of decoding them every time they are executed.
+    /// Pays off for programs spending their time in loops, the cache is kept up to date on self-modifying writes.
+    pub fn enable_decode_cache(&mut self) {
+        self.decode_cache = Some(Vec::new());
+    }
+
+    fn clear_decode_cache(&mut self) {
+        if let Some(cache) = self.decode_cache.as_mut() {
+            cache.clear();
+        }
+    }
+
+    /// Keep track of writes to addresses, that were executed as part of an instruction before.
+    /// If warn is set, every such write is reported on stderr as it happens.
+    /// Tracking starts with the next instruction, code executed before is not known.
+    pub fn track_self_modifications(&mut self, warn: bool) {
+        self.self_modifications = Some(SelfModifications::new(warn));
+    }
+
+    /// The writes to code so far, if tracking was enabled with track_self_modifications.
+    pub fn self_modifications(&self) -> Option<&SelfModifications<M::Cell>> {
+        self.self_modifications.as_ref()
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Restrict further execution, see Limits.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Number of instructions executed so far, which is what Limits::max_instructions is compared to.
+    pub fn executed_instructions(&self) -> u64 {
+        self.executed
+    }
+
+    /// Fails if executing another instruction would exceed the instruction limit or the deadline.
+    fn check_limits(&self, pc: usize, opcode: Opcode) -> Result<(), IntcodeError> {
+        if let Some(limit) = self.limits.max_instructions {
+            if self.executed >= limit {
+                return Err(IntcodeError::InstructionLimit { pc, opcode, limit });
+            }
+        }
+        if let Some(deadline) = self.limits.deadline {
+            if self.executed.is_multiple_of(DEADLINE_CHECK_INTERVAL) && Instant::now() >= deadline {
+                return Err(IntcodeError::DeadlineExceeded { pc, opcode });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn arithmetic(&self) -> Arithmetic {
+        self.arithmetic
+    }
+
+    /// Choose how overflowing additions and multiplications are treated, see Arithmetic.
+    pub fn set_arithmetic(&mut self, arithmetic: Arithmetic) {
+        self.arithmetic = arithmetic;
+    }
+
+    /// Queues a value to be read by the next input instruction.
+    pub fn push_input(&mut self, value: M::Cell) {
+        self.input.push_back(value);
+    }
+
+    /// Execute a single instruction.
+    /// Returns None for instructions that do not concern the host, otherwise the corresponding IoEvent.
+    /// If the program wants to read, but there is no queued input, the instruction is not executed.
+    pub fn step(&mut self) -> Result<Option<IoEvent<M::Cell>>, IntcodeError> {
+        self.execute(None)
+    }
+
+    /// Execute a single instruction like step and pass a record of it to the tracer.
+    pub fn step_traced(
+        &mut self,
+        tracer: &mut dyn Tracer<M::Cell>,
+    ) -> Result<Option<IoEvent<M::Cell>>, IntcodeError> {
+        self.execute(Some(tracer))
+    }
+
+    /// The opcode at pc, failing if the value is too large to be one.
+    fn opcode_at(&self, pc: usize) -> Result<Opcode, IntcodeError> {
+        let value = &self.memory[pc];
+        value
+            .to_opcode()
+            .ok_or_else(|| IntcodeError::OpcodeOverflow {
+                pc,
+                value: value.to_string(),
+            })
+    }
+
+    /// Decode the instruction at pc, using the decode cache if it is enabled.
+    fn decode(&mut self, pc: usize) -> Result<Instruction, IntcodeError> {
+        if let Some(Some(decoded)) = self.decode_cache.as_ref().and_then(|cache| cache.get(pc)) {
+            return Ok(*decoded);
+        }
+
+        let decoded = Instruction::decode(self.opcode_at(pc)?, pc)?;
+        if let Some(cache) = self.decode_cache.as_mut() {
+            if pc < DECODE_CACHE_LIMIT {
+                if pc >= cache.len() {
+                    cache.resize(pc + 1, None);
+                }
+                cache[pc] = Some(decoded);
+            }
+        }
+        Ok(decoded)
+    }
+
+    fn execute(
+        &mut self,
+        tracer: Option<&mut dyn Tracer<M::Cell>>,
+    ) -> Result<Option<IoEvent<M::Cell>>, IntcodeError> {
+        if self.halted {
+            return Ok(Some(IoEvent::Halted));
+        }
+
+        let pc = self.pc;
+        let decoded = self.decode(pc)?;
+        let opcode = decoded.opcode;
+        self.check_limits(pc, opcode)?;
+        let count = decoded.parameter_count();
+        let program = &mut self.memory;
+
+        // determine parameter addresses according to parameter modes
+        // the needed values are right after the opcode
+        let relative_base = self.relative_base;
+        let mut parameter_adrs = [0usize; 3];
+        for (i, (adr, mode)) in parameter_adrs
+            .iter_mut()
+            .zip(decoded.modes.iter())
+            .enumerate()
+            .take(count)
+        {
+            let value = to_opcode(&program[pc + 1 + i], pc, opcode)?;
+            *adr = match mode {
+                Mode::Position => to_address(value, pc, opcode)?,
+                Mode::Immediate => pc + 1 + i,
+                Mode::Relative => {
+                    let adr = (relative_base as Opcode)
+                        .checked_add(value)
+                        .ok_or(IntcodeError::Overflow { pc, opcode })?;
+                    to_address(adr, pc, opcode)?
+                }
+            };
+        }
+
+        let mut params: [M::Cell; 3] = Default::default();
+        for (param, &adr) in params.iter_mut().zip(parameter_adrs.iter()).take(count) {
+            *param = program[adr].clone();
+        }
+
+        // refuse writes beyond the memory limit, before anything is changed
+        if let (Some(i), Some(max)) = (decoded.write_parameter(), self.limits.max_memory) {
+            if parameter_adrs[i] >= max {
+                return Err(IntcodeError::MemoryLimit {
+                    pc,
+                    opcode,
+                    address: parameter_adrs[i],
+                });
+            }
+        }
+
+        // the next pc, if no jump is performed
+        let mut next_pc = pc + decoded.len;
+        // the value to be written to the write parameter of the instruction
+        let mut result = None;
+        let mut event = None;
+        let zero = M::Cell::default();
+        let overflow = IntcodeError::Overflow { pc, opcode };
+
+        match decoded.instruction {
+            // halt the program
+            99 => {
+                self.halted = true;
+                next_pc = pc;
+                event = Some(IoEvent::Halted);
+            }
+            // addition
+            1 => {
+                result = Some(
+                    self.arithmetic
+                        .add(&params[0], &params[1])
+                        .ok_or(overflow)?,
+                )
+            }
+            // multiplication
+            2 => {
+                result = Some(
+                    self.arithmetic
+                        .mul(&params[0], &params[1])
+                        .ok_or(overflow)?,
+                )
+            }
+            // input
+            3 => match self.input.pop_front() {
+                Some(val) => result = Some(val),
+                // the pc is not moved on, so the instruction is retried once input is available
+                None => return Ok(Some(IoEvent::NeedsInput)),
+            },
+            // output
+            4 => event = Some(IoEvent::Output(params[0].clone())),
+            // jump not equal
+            5 => {
+                if params[0] != zero {
+                    next_pc = to_address(to_opcode(&params[1], pc, opcode)?, pc, opcode)?;
+                }
+            }
+            // jump equal
+            6 => {
+                if params[0] == zero {
+                    next_pc = to_address(to_opcode(&params[1], pc, opcode)?, pc, opcode)?;
+                }
+            }
+            // less than
+            7 => result = Some(Opcode::from(params[0] < params[1]).into()),
+            // equality
+            8 => result = Some(Opcode::from(params[0] == params[1]).into()),
+            // adjust relative base
+            9 => {
+                self.relative_base = self
+                    .relative_base
+                    .checked_add(to_opcode(&params[0], pc, opcode)? as isize)
+                    .ok_or(overflow)?
+            }
+            // every instruction accepted by Instruction::decode is handled above
+            _ => unreachable!(),
+        };
+
+        let write = decoded
+            .write_parameter()
+            .zip(result)
+            .map(|(i, value)| (parameter_adrs[i], value));
+        if let Some(tracked) = self.self_modifications.as_mut() {
+            tracked.executed(pc, decoded.len);
+            if let Some((adr, value)) = &write {
+                tracked.write(pc, *adr, &program[*adr], value);
+            }
+        }
+        if let Some((adr, value)) = &write {
+            program[*adr] = value.clone();
+            // self-modifying code: the instruction decoded at this address is outdated now
+            if let Some(cached) = self
+                .decode_cache
+                .as_mut()
+                .and_then(|cache| cache.get_mut(*adr))
+            {
+                *cached = None;
+            }
+        }
+
+        if let Some(tracer) = tracer {
+            let write_parameter = decoded.write_parameter();
+            let [first, second, third] = params;
+            tracer.trace(&TraceRecord {
+                pc,
+                instruction: decoded,
+                relative_base,
+                addresses: parameter_adrs[..count].to_vec(),
+                reads: vec![first, second, third]
+                    .into_iter()
+                    .take(count)
+                    .enumerate()
+                    .filter(|&(i, _)| Some(i) != write_parameter)
+                    .map(|(_, value)| value)
+                    .collect(),
+                write,
+            });
+        }
+
+        self.pc = next_pc;
+        self.executed += 1;
+        Ok(event)
+    }
+
+    /// Execute instructions until the program needs input, outputs something or halts.
+    pub fn run_until_io(&mut self) -> Result<IoEvent<M::Cell>, IntcodeError> {
+        loop {
+            if let Some(event) = self.step()? {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Execute at most budget instructions, stopping early like run_until_io.
+    /// Returns None if the budget was used up before any IoEvent, so that a host can do other work,
+    /// for example an async executor running other tasks, before continuing.
+    pub fn run_for(&mut self, budget: u64) -> Result<Option<IoEvent<M::Cell>>, IntcodeError> {
+        for _ in 0..budget {
+            if let Some(event) = self.step()? {
+                return Ok(Some(event));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Run the program until it halts, doing all IO through inout.
+    pub fn run<T: IntcodeIoMut<M::Cell>>(&mut self, inout: T) -> Result<(), IntcodeError> {
+        self.run_with(inout, None)
+    }
+
+    /// Run the program like run and pass a record of every executed instruction to the tracer.
+    pub fn run_traced<T: IntcodeIoMut<M::Cell>>(
+        &mut self,
+        inout: T,
+        tracer: &mut dyn Tracer<M::Cell>,
+    ) -> Result<(), IntcodeError> {
+        self.run_with(inout, Some(tracer))
+    }
+
+    fn run_with<T: IntcodeIoMut<M::Cell>>(
+        &mut self,
+        mut inout: T,
+        mut tracer: Option<&mut dyn Tracer<M::Cell>>,
+    ) -> Result<(), IntcodeError> {
+        loop {
+            let pc = self.pc;
+            // Only used after the instruction was decoded successfully, so the opcode fits.
+            let opcode = self.memory[pc].to_opcode().unwrap_or(Opcode::MAX);
+            let io_error = |message| IntcodeError::Io {
+                pc,
+                opcode,
+                message,
+            };
+
+            // Reborrow the tracer, so that it can be used again in the next iteration.
+            let tracer = tracer
+                .as_mut()
+                .map(|tracer| &mut **tracer as &mut dyn Tracer<M::Cell>);
+            match self.execute(tracer)? {
+                None => (),
+                Some(IoEvent::NeedsInput) => {
+                    let val = inout.input().map_err(io_error)?;
+                    self.push_input(val);
+                }
+                Some(IoEvent::Output(val)) => inout.output(val).map_err(io_error)?,
+                Some(IoEvent::Halted) => return Ok(()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine(program: &[Opcode]) -> Machine {
+        Machine::new(program.iter().cloned().collect())
+    }
+
+    #[test]
+    fn test_run_until_io() {
+        // Reads a value, outputs it doubled and halts.
+        let mut m = machine(&[3, 9, 102, 2, 9, 9, 4, 9, 99, 0]);
+
+        assert_eq!(m.run_until_io(), Ok(IoEvent::NeedsInput));
+        assert_eq!(m.pc(), 0);
+        // Asking again without providing input does not change anything.
+        assert_eq!(m.run_until_io(), Ok(IoEvent::NeedsInput));
+
+        m.push_input(21);
+        assert_eq!(m.run_until_io(), Ok(IoEvent::Output(42)));
+        assert_eq!(m.run_until_io(), Ok(IoEvent::Halted));
+        assert!(m.is_halted());
+        assert_eq!(m.run_until_io(), Ok(IoEvent::Halted));
+    }
+
+    #[test]
+    fn test_run_for() {
+        // Counts down from 3 and outputs 0 afterwards.
+        let mut m = machine(&[1001, 9, -1, 9, 1005, 9, 0, 4, 9, 3]);
+
+        assert_eq!(m.run_for(3), Ok(None));
+        assert_eq!(m.executed_instructions(), 3);
+        assert_eq!(m.run_for(100), Ok(Some(IoEvent::Output(0))));
+        assert_eq!(m.executed_instructions(), 7);
+        assert_eq!(m.run_for(0), Ok(None));
+    }
+
+    #[test]
+    fn test_step() {
+        let mut m = machine(&[109, 19, 204, -19, 99]);
+
+        assert_eq!(m.step(), Ok(None));
+        assert_eq!(m.relative_base(), 19);
+        assert_eq!(m.step(), Ok(Some(IoEvent::Output(109))));
+        assert_eq!(m.pc(), 4);
+        assert_eq!(m.step(), Ok(Some(IoEvent::Halted)));
+    }
+
+    #[test]
+    fn test_quine() {
+        // The sample program from day 9, which outputs a copy of itself.
+        let program = [
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        let mut m = machine(&program);
+
+        let mut output = Vec::new();
+        while let Ok(IoEvent::Output(val)) = m.run_until_io() {
+            output.push(val);
+        }
+        assert_eq!(output, program);
+    }
+
+    #[test]
+    fn test_decode_cache_self_modifying() {
+        // Runs the instruction at 4 twice, but rewrites it from output to halt in between.
+        let program = [1101, 0, 4, 13, 4, 13, 1101, 0, 99, 4, 1105, 1, 4, 0];
+        let mut m = machine(&program);
+        m.enable_decode_cache();
+
+        assert_eq!(m.run_until_io(), Ok(IoEvent::Output(4)));
+        assert_eq!(m.run_until_io(), Ok(IoEvent::Halted));
+        assert_eq!(m.pc(), 4);
+
+        // Changing memory by hand is noticed as well.
+        let mut m = machine(&[104, 1, 1106, 0, 0]);
+        m.enable_decode_cache();
+        assert_eq!(m.run_until_io(), Ok(IoEvent::Output(1)));
+        assert_eq!(m.run_until_io(), Ok(IoEvent::Output(1)));
+        m.memory_mut()[0] = 99;
+        assert_eq!(m.run_until_io(), Ok(IoEvent::Halted));
+    }
+
+    #[test]
+    fn test_matches_reference() {
+        let program = crate::parse_program(include_str!("../../09/input.txt")).unwrap();
+
+        struct Io(std::cell::RefCell<Vec<Opcode>>);
+        impl crate::IntcodeIo for Io {
+            fn read(&self) -> Opcode {
+                1
+            }
+            fn write(&self, value: &Opcode) {
+                self.0.borrow_mut().push(*value);
+            }
+        }
+
+        let expected = Io(Default::default());
+        let mut reference_memory = program.clone();
+        crate::reference::try_run_program(&mut reference_memory, &expected).unwrap();
+
+        let actual = Io(Default::default());
+        let mut m = Machine::new(program);
+        m.enable_decode_cache();
+        m.run(&actual).unwrap();
+
+        assert_eq!(actual.0.into_inner(), expected.0.into_inner());
+        assert!(m.memory().iter().eq(reference_memory.iter()));
+    }
+
+    /// Squares the value at address 9 and outputs it, forever.
+    const SQUARES: [Opcode; 10] = [2, 9, 9, 9, 4, 9, 1105, 1, 0, 3];
+
+    #[test]
+    fn test_checked_overflow() {
+        let mut m = Machine::new(SQUARES.iter().cloned().collect::<ProgramMemory>());
+        m.set_arithmetic(Arithmetic::Checked);
+        for expected in [9, 81, 6561, 43046721, 1853020188851841] {
+            assert_eq!(m.run_until_io(), Ok(IoEvent::Output(expected)));
+        }
+        assert_eq!(
+            m.run_until_io(),
+            Err(IntcodeError::Overflow { pc: 0, opcode: 2 })
+        );
+
+        let mut m = Machine::new(SQUARES.iter().cloned().collect::<ProgramMemory>());
+        for _ in 0..5 {
+            m.run_until_io().unwrap();
+        }
+        assert_eq!(
+            m.run_until_io(),
+            Ok(IoEvent::Output(
+                1853020188851841i64.wrapping_mul(1853020188851841)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_big_int() {
+        use crate::{BigInt, BigProgramMemory};
+
+        for arithmetic in [Arithmetic::Wrapping, Arithmetic::Checked] {
+            let memory: BigProgramMemory = SQUARES.iter().map(|&v| BigInt::from(v)).collect();
+            let mut m = Machine::new(memory);
+            m.set_arithmetic(arithmetic);
+            for _ in 0..5 {
+                m.run_until_io().unwrap();
+            }
+            assert_eq!(
+                m.run_until_io(),
+                Ok(IoEvent::Output(BigInt::from(3).pow(64)))
+            );
+        }
+    }
+
+    #[test]
+    fn test_big_opcode() {
+        use crate::{BigInt, BigProgramMemory};
+
+        let too_large = BigInt::from(2).pow(64);
+        let memory: BigProgramMemory = vec![too_large.clone()].into_iter().collect();
+        let err = Machine::new(memory).step().unwrap_err();
+        assert_eq!(
+            err,
+            IntcodeError::OpcodeOverflow {
+                pc: 0,
+                value: too_large.to_string()
+            }
+        );
+        assert_eq!(err.opcode(), None);
+    }
+}
@@ -1,8 +1,111 @@
-use intcode_computer::run_program_from_file;
+use intcode_computer::{
+    differential, disassemble, profile_program_from_file, read_binary_program,
+    read_program_from_file, run_program_from_file, run_program_from_file_with_self_modifications,
+    trace_program_from_file, write_binary_program, ControlFlowGraph, Debugger, Machine, Replayer,
+    Snapshot,
+};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
 
 fn main() {
     let mut args = std::env::args();
     args.next();
-    let filename = args.next().unwrap();
-    run_program_from_file(&filename);
+    let first = args.next().unwrap();
+
+    match first.as_str() {
+        // Print the listing instead of running the program.
+        "--disasm" => {
+            let filename = args.next().unwrap();
+            print!("{}", disassemble(&read_program_from_file(&filename)));
+        }
+        // Print the control flow graph of the program in the Graphviz DOT language.
+        "--cfg" => {
+            let filename = args.next().unwrap();
+            let program = read_program_from_file(&filename);
+            print!("{}", ControlFlowGraph::build(&program).to_dot(&program));
+        }
+        // Run the program and write a line for every executed instruction to a trace file.
+        "--trace" => {
+            let trace_filename = args.next().unwrap();
+            let filename = args.next().unwrap();
+            trace_program_from_file(&filename, &trace_filename);
+        }
+        // Run the program and write a profile with execution counts and coverage to a report file.
+        "--profile" => {
+            let report_filename = args.next().unwrap();
+            let filename = args.next().unwrap();
+            profile_program_from_file(&filename, &report_filename);
+        }
+        // Run the program and report every write to code that was executed before.
+        "--self-modifications" => {
+            let filename = args.next().unwrap();
+            run_program_from_file_with_self_modifications(&filename);
+        }
+        // Run the program in the interactive debugger.
+        "debug" => {
+            let filename = args.next().unwrap();
+            let mut debugger = Debugger::new(read_program_from_file(&filename));
+            let stdin = io::stdin();
+            debugger.repl(stdin.lock(), io::stdout()).unwrap();
+        }
+        // Convert a text program (or snapshot) to the binary format, optionally with a checksum.
+        "encode" | "encode-snapshot" => {
+            let mut input = args.next().unwrap();
+            let with_checksum = input == "--checksum";
+            if with_checksum {
+                input = args.next().unwrap();
+            }
+            let output = args.next().unwrap();
+            let writer = BufWriter::new(File::create(&output).unwrap());
+            if first == "encode" {
+                write_binary_program(&read_program_from_file(&input), writer, with_checksum)
+            } else {
+                Snapshot::load_from_file(&input)
+                    .unwrap_or_else(|err| panic!("{}: {}", input, err))
+                    .save_binary(writer, with_checksum)
+            }
+            .unwrap_or_else(|err| panic!("{}: {}", output, err));
+        }
+        // Convert a binary program (or snapshot) back to the text format.
+        "decode" | "decode-snapshot" => {
+            let input = args.next().unwrap();
+            let output = args.next().unwrap();
+            let reader = BufReader::new(File::open(&input).unwrap());
+            let result = if first == "decode" {
+                read_binary_program(reader).and_then(|program| {
+                    let values: Vec<String> = program.iter().map(|v| v.to_string()).collect();
+                    fs::write(&output, values.join(",") + "\n")
+                })
+            } else {
+                Snapshot::load_binary(reader).and_then(|snapshot| snapshot.save_to_file(&output))
+            };
+            result.unwrap_or_else(|err| panic!("{}", err));
+        }
+        // Run the program with the inputs of a recorded session and check that it produces the same outputs.
+        "replay" => {
+            let session = args.next().unwrap();
+            let filename = args.next().unwrap();
+            let mut replayer = Replayer::load_from_file(&session)
+                .unwrap_or_else(|err| panic!("{}: {}", session, err));
+            let events = replayer.remaining();
+            let mut machine = Machine::new(read_program_from_file(&filename));
+            replayer
+                .run(&mut machine)
+                .unwrap_or_else(|err| panic!("{}: {}", session, err));
+            println!("Replayed {} events", events);
+        }
+        // Run the program with this crate and with intcode_computer.py and report the first difference.
+        // The remaining arguments are the inputs, for example `difftest ../intcode_computer.py ../05/input.txt 5`.
+        "difftest" => {
+            let script = args.next().unwrap();
+            let filename = args.next().unwrap();
+            let inputs: Vec<_> = args.map(|arg| arg.parse().unwrap()).collect();
+            match differential::compare_with_python(&script, &filename, &inputs) {
+                Ok(None) => println!("No divergence"),
+                Ok(Some(divergence)) => println!("{}", divergence),
+                Err(err) => panic!("{}", err),
+            }
+        }
+        filename => run_program_from_file(filename),
+    }
 }
@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::iter::FromIterator;
+use std::ops::{Index, IndexMut};
+
+use crate::{Cell, InfiniteVector, Opcode};
+
+/// Storage for the memory of an Intcode program, in which every address that was not written to holds 0.
+/// Machine and run_program are generic over it, so that the backend can be chosen by the program's needs.
+pub trait Memory:
+    Index<usize, Output = <Self as Memory>::Cell>
+    + IndexMut<usize>
+    + FromIterator<<Self as Memory>::Cell>
+    + Clone
+    + Default
+{
+    /// The type of the values, Opcode for all but the big integer memory.
+    type Cell: Cell;
+}
+
+/// The dense backend, growing a Vec until it covers the highest written address.
+impl<C: Cell> Memory for InfiniteVector<C> {
+    type Cell = C;
+}
+
+/// Number of values in a single page of SparseMemory.
+const PAGE_SIZE: usize = 1024;
+
+/// Read by indexing a page of SparseMemory that was never written to.
+static ZERO: Opcode = 0;
+
+/// The sparse backend, only allocating fixed size pages around the addresses that were written to.
+/// A little slower than the dense ProgramMemory, but writing to address 10^12 costs a single page.
+#[derive(Default, Clone, Debug)]
+pub struct SparseMemory {
+    // pages indexed by address / PAGE_SIZE
+    pages: HashMap<usize, Box<[Opcode; PAGE_SIZE]>>,
+}
+
+impl SparseMemory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of allocated pages.
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+}
+
+impl Memory for SparseMemory {
+    type Cell = Opcode;
+}
+
+impl FromIterator<Opcode> for SparseMemory {
+    fn from_iter<I: IntoIterator<Item = Opcode>>(iter: I) -> Self {
+        let mut memory = Self::new();
+        for (adr, value) in iter.into_iter().enumerate() {
+            if value != 0 {
+                memory[adr] = value;
+            }
+        }
+        memory
+    }
+}
+
+impl Index<usize> for SparseMemory {
+    type Output = Opcode;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        match self.pages.get(&(index / PAGE_SIZE)) {
+            Some(page) => &page[index % PAGE_SIZE],
+            None => &ZERO,
+        }
+    }
+}
+
+impl IndexMut<usize> for SparseMemory {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        let page = self
+            .pages
+            .entry(index / PAGE_SIZE)
+            .or_insert_with(|| Box::new([0; PAGE_SIZE]));
+        &mut page[index % PAGE_SIZE]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IoEvent, Machine};
+
+    #[test]
+    fn test_sparse_memory() {
+        let mut memory: SparseMemory = vec![1, 0, 2].into_iter().collect();
+        assert_eq!(memory.page_count(), 1);
+        assert_eq!((memory[0], memory[1], memory[2]), (1, 0, 2));
+
+        memory[1_000_000_000_000] = 7;
+        assert_eq!(memory[1_000_000_000_000], 7);
+        assert_eq!(memory[1_000_000_000_001], 0);
+        assert_eq!(memory.page_count(), 2);
+    }
+
+    #[test]
+    fn test_far_away_addresses() {
+        // Stores the input at 10^12 and outputs it again.
+        let program = [3, 1_000_000_000_000, 4, 1_000_000_000_000, 99];
+        let mut machine: Machine<SparseMemory> = Machine::new(program.iter().cloned().collect());
+        machine.push_input(42);
+        assert_eq!(machine.run_until_io(), Ok(IoEvent::Output(42)));
+        assert_eq!(machine.run_until_io(), Ok(IoEvent::Halted));
+        assert_eq!(machine.memory().page_count(), 2);
+    }
+}
@@ -0,0 +1,366 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::disasm::{decode_at, format_instruction, DATA_VALUES_PER_LINE};
+use crate::{Instruction, Mode, Opcode, ProgramMemory, TraceRecord, Tracer};
+
+/// Number of rows in every table of the report.
+const REPORT_ROWS: usize = 10;
+
+/// A loop found through a backward jump that was taken at least once.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HotLoop {
+    /// The target of the jump, that is the first instruction of the loop.
+    pub start: usize,
+    /// The address of the jump instruction, that is the last instruction of the loop.
+    pub end: usize,
+    /// How often the jump back to start was taken.
+    pub iterations: u64,
+    /// Number of instructions executed between start and end (including both).
+    pub executed: u64,
+}
+
+/// A line of the annotated disassembly.
+enum Entry {
+    Instruction(usize, Instruction),
+    Data(usize, Vec<Opcode>),
+}
+
+/// Counts executions per pc and per opcode and memory accesses per address.
+/// Pass it to Machine::run_traced (or any other traced run) and call report afterwards.
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    executed: u64,
+    pc_counts: BTreeMap<usize, u64>,
+    opcode_counts: BTreeMap<Opcode, u64>,
+    reads: BTreeMap<usize, u64>,
+    writes: BTreeMap<usize, u64>,
+    // taken backward jumps by (target, source)
+    back_edges: BTreeMap<(usize, usize), u64>,
+    // pc and decoded previous instruction, to detect taken jumps
+    last: Option<(usize, Instruction)>,
+}
+
+impl<C> Tracer<C> for Profiler {
+    fn trace(&mut self, record: &TraceRecord<C>) {
+        let pc = record.pc;
+        let decoded = &record.instruction;
+
+        // Only jumps to a constant target make a loop, computed jumps backwards are usually returns from functions.
+        // Other instructions may be followed by any pc as well, if one profiler traces several runs.
+        if let Some((last_pc, last)) = self.last {
+            let is_jump = matches!(last.instruction, 5 | 6) && last.modes[1] == Mode::Immediate;
+            if is_jump && pc != last_pc + last.len && pc <= last_pc {
+                *self.back_edges.entry((pc, last_pc)).or_default() += 1;
+            }
+        }
+        self.last = Some((pc, *decoded));
+
+        self.executed += 1;
+        *self.pc_counts.entry(pc).or_default() += 1;
+        *self.opcode_counts.entry(decoded.opcode).or_default() += 1;
+
+        // Immediate parameters are part of the code, only values fetched from an address count as reads.
+        let write_parameter = decoded.write_parameter();
+        for (i, &adr) in record.addresses.iter().enumerate() {
+            if Some(i) != write_parameter && decoded.modes[i] != Mode::Immediate {
+                *self.reads.entry(adr).or_default() += 1;
+            }
+        }
+        if let Some((adr, _)) = &record.write {
+            *self.writes.entry(*adr).or_default() += 1;
+        }
+    }
+}
+
+/// The part of all executed instructions, formatted as a percentage.
+fn percentage(count: u64, total: u64) -> String {
+    format!("{:.1}", count as f64 * 100.0 / total.max(1) as f64)
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of instructions traced so far.
+    pub fn executed(&self) -> u64 {
+        self.executed
+    }
+
+    /// How often the instruction at pc was executed.
+    pub fn pc_count(&self, pc: usize) -> u64 {
+        self.pc_counts.get(&pc).cloned().unwrap_or(0)
+    }
+
+    /// How often each opcode (including the parameter modes) was executed.
+    pub fn opcode_counts(&self) -> &BTreeMap<Opcode, u64> {
+        &self.opcode_counts
+    }
+
+    /// How often the value at the address was read by a parameter in position or relative mode.
+    pub fn reads(&self, address: usize) -> u64 {
+        self.reads.get(&address).cloned().unwrap_or(0)
+    }
+
+    /// How often the address was written to.
+    pub fn writes(&self, address: usize) -> u64 {
+        self.writes.get(&address).cloned().unwrap_or(0)
+    }
+
+    /// All loops that were run at least once, the ones executing the most instructions first.
+    pub fn hot_loops(&self) -> Vec<HotLoop> {
+        let mut loops: Vec<HotLoop> = self
+            .back_edges
+            .iter()
+            .map(|(&(start, end), &iterations)| HotLoop {
+                start,
+                end,
+                iterations,
+                executed: self.pc_counts.range(start..=end).map(|(_, &n)| n).sum(),
+            })
+            .collect();
+        loops.sort_by(|a, b| b.executed.cmp(&a.executed).then(a.start.cmp(&b.start)));
+        loops
+    }
+
+    /// Splits the program into instructions and data like disassemble.
+    /// Executed addresses are always listed as instructions, so that the listing does not get out of step
+    /// when data is placed between the instructions.
+    fn entries(&self, program: &ProgramMemory) -> Vec<Entry> {
+        let executed = |pc| self.pc_counts.contains_key(&pc);
+        // Instructions that were not executed must not hide an executed one in their parameters.
+        let decode = |pc: usize| {
+            decode_at(program, pc)
+                .filter(|decoded| executed(pc) || !(pc + 1..pc + decoded.len).any(executed))
+        };
+
+        let mut entries = Vec::new();
+        let mut pc = 0;
+        while pc < program.len() {
+            match decode(pc) {
+                Some(decoded) => {
+                    entries.push(Entry::Instruction(pc, decoded));
+                    pc += decoded.len;
+                }
+                None => {
+                    let start = pc;
+                    let mut values = Vec::new();
+                    while pc < program.len()
+                        && values.len() < DATA_VALUES_PER_LINE
+                        && (pc == start || decode(pc).is_none())
+                    {
+                        values.push(program[pc]);
+                        pc += 1;
+                    }
+                    entries.push(Entry::Data(start, values));
+                }
+            }
+        }
+        entries
+    }
+
+    /// Ranges (start inclusive, end exclusive) of consecutive instructions that were never executed.
+    /// Since code and data cannot be told apart, data that happens to decode is included as well.
+    pub fn unexecuted(&self, program: &ProgramMemory) -> Vec<(usize, usize)> {
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        let mut in_range = false;
+        for entry in self.entries(program) {
+            match entry {
+                Entry::Instruction(pc, decoded) if self.pc_count(pc) == 0 => {
+                    match ranges.last_mut() {
+                        Some(range) if in_range => range.1 = pc + decoded.len,
+                        _ => ranges.push((pc, pc + decoded.len)),
+                    }
+                    in_range = true;
+                }
+                _ => in_range = false,
+            }
+        }
+        ranges
+    }
+
+    /// The listing of disassemble, with the number of executions in front of every instruction.
+    /// Instructions that were never executed are marked with a `-`.
+    /// program should be the memory before running, since self-modifying code changes it.
+    pub fn annotate(&self, program: &ProgramMemory) -> String {
+        let mut listing = String::new();
+        for entry in self.entries(program) {
+            match entry {
+                Entry::Instruction(pc, decoded) => {
+                    let count = match self.pc_count(pc) {
+                        0 => String::from("-"),
+                        n => n.to_string(),
+                    };
+                    let line = format_instruction(program, pc, &decoded);
+                    writeln!(listing, "{:>10} {:04}: {}", count, pc, line).unwrap();
+                }
+                Entry::Data(pc, values) => {
+                    let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+                    writeln!(listing, "{:>10} {:04}: DATA {}", "", pc, values.join(", ")).unwrap();
+                }
+            }
+        }
+        listing
+    }
+
+    /// Tables of the executed opcodes, the hottest instructions, loops and addresses and the code that never ran.
+    pub fn report(&self, program: &ProgramMemory) -> String {
+        let total = self.executed;
+        let mut report = String::new();
+        writeln!(report, "executed instructions: {}", total).unwrap();
+
+        writeln!(report, "\nopcode mnemonic      count      %").unwrap();
+        let mut opcodes: Vec<_> = self.opcode_counts.iter().collect();
+        opcodes.sort_by(|a, b| b.1.cmp(a.1));
+        for (&opcode, &count) in opcodes {
+            let mnemonic = Instruction::decode(opcode, 0).map_or("?", |decoded| decoded.mnemonic());
+            let percent = percentage(count, total);
+            writeln!(
+                report,
+                "{:>6} {:<8} {:>10} {:>6}",
+                opcode, mnemonic, count, percent
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            report,
+            "\nhot instructions\n    pc      count      %  instruction"
+        )
+        .unwrap();
+        let mut pcs: Vec<_> = self.pc_counts.iter().collect();
+        pcs.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (&pc, &count) in pcs.into_iter().take(REPORT_ROWS) {
+            let line = decode_at(program, pc).map_or(String::from("?"), |decoded| {
+                format_instruction(program, pc, &decoded)
+            });
+            let percent = percentage(count, total);
+            writeln!(report, "  {:04} {:>10} {:>6}  {}", pc, count, percent, line).unwrap();
+        }
+
+        writeln!(
+            report,
+            "\nhot loops\n start   end iterations   executed      %"
+        )
+        .unwrap();
+        for hot in self.hot_loops().into_iter().take(REPORT_ROWS) {
+            let percent = percentage(hot.executed, total);
+            writeln!(
+                report,
+                "  {:04}  {:04} {:>10} {:>10} {:>6}",
+                hot.start, hot.end, hot.iterations, hot.executed, percent
+            )
+            .unwrap();
+        }
+
+        writeln!(report, "\nhot addresses\naddress      reads     writes").unwrap();
+        let mut addresses: Vec<usize> = self
+            .reads
+            .keys()
+            .chain(self.writes.keys())
+            .cloned()
+            .collect();
+        addresses.sort_unstable();
+        addresses.dedup();
+        addresses.sort_by_key(|&adr| std::cmp::Reverse(self.reads(adr) + self.writes(adr)));
+        for adr in addresses.into_iter().take(REPORT_ROWS) {
+            writeln!(
+                report,
+                "   {:04} {:>10} {:>10}",
+                adr,
+                self.reads(adr),
+                self.writes(adr)
+            )
+            .unwrap();
+        }
+
+        writeln!(report, "\nnever executed").unwrap();
+        for (start, end) in self.unexecuted(program) {
+            writeln!(report, "  {:04}..{:04}", start, end).unwrap();
+        }
+        report
+    }
+
+    /// The report followed by the annotated listing, as written by `profile`.
+    /// program should be the memory the run started with, like for annotate.
+    pub fn full_report(&self, program: &ProgramMemory) -> String {
+        self.report(program) + "\n" + &self.annotate(program)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assemble, Machine, VecIo};
+
+    const COUNTDOWN: &str = "
+         ADD #3, #0 -> [counter]
+loop:    ADD [counter], #-1 -> [counter]
+         JNZ [counter], #loop
+         HLT
+         OUT #1
+counter: DATA 0
+";
+
+    fn profile(source: &str) -> (ProgramMemory, Profiler) {
+        let program = assemble(source).unwrap();
+        let mut profiler = Profiler::new();
+        let mut machine = Machine::new(program.clone());
+        machine
+            .run_traced(VecIo::new(vec![]), &mut profiler)
+            .unwrap();
+        (program, profiler)
+    }
+
+    #[test]
+    fn test_counts() {
+        let (_, profiler) = profile(COUNTDOWN);
+        assert_eq!(profiler.executed(), 8);
+        assert_eq!(profiler.pc_count(0), 1);
+        assert_eq!(profiler.pc_count(4), 3);
+        assert_eq!(profiler.pc_count(8), 3);
+        assert_eq!(profiler.pc_count(12), 0);
+        assert_eq!(profiler.opcode_counts().get(&1001), Some(&3));
+        assert_eq!(profiler.reads(14), 6);
+        assert_eq!(profiler.writes(14), 4);
+        assert_eq!(
+            profiler.hot_loops(),
+            vec![HotLoop {
+                start: 4,
+                end: 8,
+                iterations: 2,
+                executed: 6
+            }]
+        );
+    }
+
+    #[test]
+    fn test_several_runs() {
+        // The second run starts over after an ADD with an immediate second parameter, which is no jump.
+        let program = assemble("ADD #1, #2 -> [8]\nHLT").unwrap();
+        let mut profiler = Profiler::new();
+        for _ in 0..2 {
+            let mut machine = Machine::new(program.clone());
+            machine.step_traced(&mut profiler).unwrap();
+        }
+        assert_eq!(profiler.pc_count(0), 2);
+        assert_eq!(profiler.hot_loops(), vec![]);
+    }
+
+    #[test]
+    fn test_coverage() {
+        let (program, profiler) = profile(COUNTDOWN);
+        assert_eq!(profiler.unexecuted(&program), vec![(12, 14)]);
+        assert_eq!(
+            profiler.annotate(&program),
+            "         1 0000: ADD #3, #0 -> [14]
+         3 0004: ADD [14], #-1 -> [14]
+         3 0008: JNZ [14], #4
+         1 0011: HLT
+         - 0012: OUT #1
+           0014: DATA 0
+"
+        );
+    }
+}
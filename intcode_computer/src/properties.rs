@@ -0,0 +1,211 @@
+//! Property based tests of the interpreter, on random well-formed programs and on random values.
+
+use proptest::prelude::*;
+
+use crate::{
+    reference, Arithmetic, IntcodeError, Limits, Machine, Memory, Opcode, ProgramMemory,
+    SparseMemory, VecIo,
+};
+
+/// Addresses at or above this are refused, so that random programs do not allocate huge amounts of memory.
+const MAX_MEMORY: usize = 4096;
+
+const LIMITS: Limits = Limits {
+    max_instructions: Some(2000),
+    max_memory: Some(MAX_MEMORY),
+    deadline: None,
+};
+
+/// Random values, biased towards valid opcodes and small addresses, so that programs get some way.
+fn soup() -> impl Strategy<Value = Vec<Opcode>> {
+    let value = prop_oneof![
+        3 => (1..=9i64, 0..3i64, 0..3i64, 0..3i64)
+            .prop_map(|(i, a, b, c)| i + 100 * a + 1000 * b + 10000 * c),
+        1 => Just(99i64),
+        4 => -20..200i64,
+        1 => any::<i64>(),
+    ];
+    prop::collection::vec(value, 1..64)
+}
+
+/// A single instruction of a well-formed program, the jump target is resolved once all instructions are known.
+#[derive(Debug, Clone)]
+enum Generated {
+    /// ADD, MUL, LT or EQ with two read parameters, writing to a data address.
+    Arithmetic(i64, (i64, Opcode), (i64, Opcode), usize),
+    In(usize),
+    Out((i64, Opcode)),
+    /// JNZ or JZ with its condition and the number of instructions to skip.
+    Jump(i64, (i64, Opcode), usize),
+    Arb(Opcode),
+}
+
+/// Number of values after the code, that parameters in position mode refer to.
+const DATA_LEN: usize = 16;
+
+/// A read parameter as mode and value, the value of position mode is an index into the data.
+fn read_parameter() -> impl Strategy<Value = (i64, Opcode)> {
+    prop_oneof![
+        (Just(0i64), 0..DATA_LEN as Opcode),
+        (Just(1i64), -50..50i64),
+        (Just(2i64), 0..40i64),
+    ]
+}
+
+fn generated() -> impl Strategy<Value = Generated> {
+    prop_oneof![
+        4 => (prop::sample::select(vec![1i64, 2, 7, 8]), read_parameter(), read_parameter(), 0..DATA_LEN)
+            .prop_map(|(i, a, b, c)| Generated::Arithmetic(i, a, b, c)),
+        1 => (0..DATA_LEN).prop_map(Generated::In),
+        1 => read_parameter().prop_map(Generated::Out),
+        2 => (5..=6i64, read_parameter(), 1..4usize).prop_map(|(i, a, skip)| Generated::Jump(i, a, skip)),
+        1 => (-10..20i64).prop_map(Generated::Arb),
+    ]
+}
+
+/// A program, that only jumps forwards to instructions and only writes to the data after its code,
+/// so that it always terminates and its pc always points at one of its instructions.
+/// Returns the program, the addresses of all its instructions and some inputs for it.
+fn well_formed() -> impl Strategy<Value = (ProgramMemory, Vec<usize>, Vec<Opcode>)> {
+    (
+        prop::collection::vec(generated(), 1..30),
+        prop::collection::vec(-100..100i64, DATA_LEN),
+        prop::collection::vec(-100..100i64, 0..8),
+    )
+        .prop_map(|(instructions, data, inputs)| {
+            let len = |g: &Generated| match g {
+                Generated::Arithmetic(..) => 4,
+                Generated::Jump(..) => 3,
+                _ => 2,
+            };
+            let mut starts = Vec::new();
+            let mut pc = 0;
+            for g in instructions.iter() {
+                starts.push(pc);
+                pc += len(g);
+            }
+            // the final HLT
+            starts.push(pc);
+            let data_start = pc + 1;
+
+            let read = |(mode, value): (i64, Opcode)| match mode {
+                0 => data_start as Opcode + value,
+                _ => value,
+            };
+            let mut program = Vec::new();
+            for (i, g) in instructions.iter().enumerate() {
+                match *g {
+                    Generated::Arithmetic(op, a, b, c) => program.extend(vec![
+                        op + 100 * a.0 + 1000 * b.0,
+                        read(a),
+                        read(b),
+                        (data_start + c) as Opcode,
+                    ]),
+                    Generated::In(c) => program.extend(vec![3, (data_start + c) as Opcode]),
+                    Generated::Out(a) => program.extend(vec![4 + 100 * a.0, read(a)]),
+                    Generated::Jump(op, a, skip) => {
+                        let target = starts[(i + skip).min(starts.len() - 1)];
+                        program.extend(vec![op + 100 * a.0 + 1000, read(a), target as Opcode])
+                    }
+                    Generated::Arb(value) => program.extend(vec![109, value]),
+                }
+            }
+            program.push(99);
+            program.extend(data);
+            (program.into_iter().collect(), starts, inputs)
+        })
+}
+
+/// Run a program with the given memory backend and return the result, the outputs and the first MAX_MEMORY values.
+fn run<M: Memory<Cell = Opcode>>(
+    memory: M,
+    inputs: &[Opcode],
+    arithmetic: Arithmetic,
+    cached: bool,
+) -> (Result<(), IntcodeError>, Vec<Opcode>, Vec<Opcode>) {
+    let mut machine = Machine::new(memory);
+    machine.set_limits(LIMITS);
+    machine.set_arithmetic(arithmetic);
+    if cached {
+        machine.enable_decode_cache();
+    }
+    let mut io = VecIo::new(inputs.iter().cloned());
+    let result = machine.run(&mut io);
+    let memory = (0..MAX_MEMORY).map(|adr| machine.memory()[adr]).collect();
+    (result, io.into_output(), memory)
+}
+
+proptest! {
+    #[test]
+    fn test_memory_backends_agree(
+        program in soup(),
+        inputs in prop::collection::vec(-10..10i64, 0..8),
+        checked in any::<bool>(),
+    ) {
+        let arithmetic = if checked { Arithmetic::Checked } else { Arithmetic::Wrapping };
+        let dense = program.iter().cloned().collect::<ProgramMemory>();
+        let sparse = program.iter().cloned().collect::<SparseMemory>();
+
+        let expected = run(dense.clone(), &inputs, arithmetic, false);
+        prop_assert_eq!(&run(dense, &inputs, arithmetic, true), &expected);
+        prop_assert_eq!(&run(sparse, &inputs, arithmetic, false), &expected);
+    }
+
+    #[test]
+    fn test_errors_are_reported_at_pc(program in soup()) {
+        let mut machine = Machine::new(program.into_iter().collect::<ProgramMemory>());
+        machine.set_limits(LIMITS);
+        // Always provide some input, so that the machine does not wait forever.
+        loop {
+            let pc = machine.pc();
+            match machine.step() {
+                Ok(Some(crate::IoEvent::Halted)) => break,
+                Ok(Some(crate::IoEvent::NeedsInput)) => machine.push_input(1),
+                Ok(_) => (),
+                Err(err) => {
+                    // A failing instruction is not executed, so the machine stays at the instruction.
+                    prop_assert_eq!(err.pc(), pc);
+                    prop_assert_eq!(machine.pc(), pc);
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_pc_points_at_instruction((program, starts, inputs) in well_formed()) {
+        let mut machine = Machine::new(program);
+        let mut inputs = inputs.into_iter();
+        loop {
+            prop_assert!(starts.contains(&machine.pc()), "pc {} is not an instruction", machine.pc());
+            match machine.step() {
+                Ok(Some(crate::IoEvent::Halted)) => break,
+                Ok(Some(crate::IoEvent::NeedsInput)) => match inputs.next() {
+                    Some(value) => machine.push_input(value),
+                    None => break,
+                },
+                Ok(_) => (),
+                // only reads in relative mode can fail, if the relative base is moved below zero
+                Err(err) => {
+                    let is_negative_address = matches!(err, IntcodeError::NegativeAddress { .. });
+                    prop_assert!(is_negative_address, "unexpected error {}", err);
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_matches_reference((program, _, inputs) in well_formed()) {
+        // The reference interpreter fails on overflows, like checked Arithmetic.
+        let (result, output, memory) = run(program.clone(), &inputs, Arithmetic::Checked, false);
+
+        let mut reference_memory = program;
+        let mut io = VecIo::new(inputs.iter().cloned());
+        let reference_result = reference::try_run_program(&mut reference_memory, &mut io);
+
+        prop_assert_eq!(result, reference_result);
+        prop_assert_eq!(output, io.into_output());
+        prop_assert!(memory.iter().eq((0..MAX_MEMORY).map(|adr| &reference_memory[adr])));
+    }
+}
@@ -0,0 +1,199 @@
+//! The straightforward interpreter, that Machine was derived from.
+//! It decodes every instruction from scratch and allocates for its parameters,
+//! but it is kept as a reference to check the faster Machine against.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::ops::Index;
+
+use crate::instruction::OPCODE_LENGHTS_ARR;
+use crate::{IntcodeError, IntcodeIoMut, Opcode, ProgramMemory};
+
+/// Resolves the address a raw value refers to, failing for negative addresses.
+fn to_address(value: Opcode, pc: usize, opcode: Opcode) -> Result<usize, IntcodeError> {
+    value.try_into().map_err(|_| IntcodeError::NegativeAddress {
+        pc,
+        opcode,
+        address: value,
+    })
+}
+
+/// Run a program like crate::try_run_program, but with the reference interpreter.
+/// Overflows stop the program with IntcodeError::Overflow, like a Machine with checked Arithmetic.
+pub fn try_run_program<T: IntcodeIoMut>(
+    program: &mut ProgramMemory,
+    mut inout: T,
+) -> Result<(), IntcodeError> {
+    let opcode_lenghts: HashMap<u8, u8> = OPCODE_LENGHTS_ARR.iter().cloned().collect();
+
+    // program counter, starting at index 0
+    let mut pc = 0usize;
+
+    // relative base, starting at index 0
+    let mut relative_base = 0isize;
+
+    let overflow = |pc, opcode| IntcodeError::Overflow { pc, opcode };
+
+    loop {
+        // This covers the access of the opcode and all parameters (without derefferencing these).
+        program.ensure_size(pc + *opcode_lenghts.values().max().unwrap_or(&0) as usize);
+
+        // fetch the opcode and split it into instruction and modes
+        let opcode = program[pc];
+        let invalid_opcode = IntcodeError::InvalidOpcode { pc, opcode };
+        let instruction: u8 = (opcode % 100)
+            .try_into()
+            .map_err(|_| invalid_opcode.clone())?;
+        let modes = [
+            (opcode / 100) % 10,
+            (opcode / 1000) % 10,
+            (opcode / 10000) % 10,
+        ];
+
+        let op_len = *opcode_lenghts.get(&instruction).ok_or(invalid_opcode)? as usize;
+
+        // only the modes of the actual parameters are checked, the other digits are ignored
+        if let Some(&mode) = modes[..op_len - 1].iter().find(|&&mode| mode > 2) {
+            return Err(IntcodeError::InvalidMode { pc, opcode, mode });
+        }
+
+        // instructions writing to memory must not get their target parameter in immediate mode
+        let write_parameter = match instruction {
+            1 | 2 | 7 | 8 => Some(2),
+            3 => Some(0),
+            _ => None,
+        };
+        if let Some(i) = write_parameter {
+            if modes[i] == 1 {
+                return Err(IntcodeError::ImmediateWrite { pc, opcode });
+            }
+        }
+
+        // determine parameter addresses according to parameter modes
+        // the needed values are right after the opcode
+        let parameter_adrs: Vec<usize> = (0usize..(op_len - 1))
+            .map(|i| match modes[i] {
+                // position mode
+                0 => to_address(program[pc + 1 + i], pc, opcode),
+                // immediate mode
+                1 => Ok(pc + 1 + i),
+                // relative mode
+                2 => (relative_base as Opcode)
+                    .checked_add(program[pc + 1 + i])
+                    .ok_or_else(|| overflow(pc, opcode))
+                    .and_then(|adr| to_address(adr, pc, opcode)),
+                // checked above
+                _ => unreachable!(),
+            })
+            .collect::<Result<_, _>>()?;
+
+        // since we will be accessing these memory addresses, we will have to ensure that they are loaded too
+        let max_index = *parameter_adrs.iter().max().unwrap_or(&0);
+        program.ensure_size(max_index);
+
+        let params: Vec<&Opcode> = parameter_adrs
+            .iter()
+            .map(|&adr| program.index(adr))
+            .collect();
+
+        // set if a jump is performed; if not the pc will have to be incremented according to the opcode length and parameter count
+        let mut pc_jumped = false;
+
+        match instruction {
+            // halt the program
+            99 => break,
+            // addition
+            1 => {
+                program[parameter_adrs[2]] = params[0]
+                    .checked_add(*params[1])
+                    .ok_or_else(|| overflow(pc, opcode))?;
+            }
+            // multiplication
+            2 => {
+                program[parameter_adrs[2]] = params[0]
+                    .checked_mul(*params[1])
+                    .ok_or_else(|| overflow(pc, opcode))?;
+            }
+            // input
+            3 => {
+                let val = inout.input().map_err(|message| IntcodeError::Io {
+                    pc,
+                    opcode,
+                    message,
+                })?;
+                program[parameter_adrs[0]] = val;
+            }
+            // output
+            4 => {
+                inout
+                    .output(*params[0])
+                    .map_err(|message| IntcodeError::Io {
+                        pc,
+                        opcode,
+                        message,
+                    })?;
+            }
+            // jump not equal
+            5 => {
+                if *params[0] != 0 {
+                    pc_jumped = true;
+                    pc = to_address(*params[1], pc, opcode)?;
+                }
+            }
+            // jump equal
+            6 => {
+                if *params[0] == 0 {
+                    pc_jumped = true;
+                    pc = to_address(*params[1], pc, opcode)?;
+                }
+            }
+            // less than
+            7 => {
+                program[parameter_adrs[2]] = (params[0] < params[1]).into();
+            }
+            // equality
+            8 => {
+                program[parameter_adrs[2]] = (params[0] == params[1]).into();
+            }
+            // adjust relative base
+            9 => {
+                relative_base = relative_base
+                    .checked_add(*params[0] as isize)
+                    .ok_or_else(|| overflow(pc, opcode))?;
+            }
+            // every instruction in OPCODE_LENGHTS_ARR is handled above
+            _ => unreachable!(),
+        };
+
+        // pc should not be incremented if the current instruction triggered a jump
+        if !pc_jumped {
+            pc += op_len;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VecIo;
+
+    #[test]
+    fn test_overflow() {
+        // programs and the pc and opcode of the instruction that overflows
+        let programs = vec![
+            (vec![1101, Opcode::MAX, 1, 0, 99], 0, 1101),
+            (vec![1102, Opcode::MAX, 2, 0, 99], 0, 1102),
+            (vec![109, Opcode::MAX, 109, 1, 99], 2, 109),
+            (vec![109, Opcode::MAX, 1201, 1, 0, 0, 99], 2, 1201),
+        ];
+        for (program, pc, opcode) in programs {
+            let mut program: ProgramMemory = program.into_iter().collect();
+            assert_eq!(
+                try_run_program(&mut program, VecIo::default()),
+                Err(IntcodeError::Overflow { pc, opcode })
+            );
+        }
+    }
+}
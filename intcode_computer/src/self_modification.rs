@@ -0,0 +1,158 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use crate::Opcode;
+
+/// A write by the program, that changed the value at an address, which was part of an executed instruction before.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelfModification<C = Opcode> {
+    /// Address of the writing instruction.
+    pub pc: usize,
+    pub address: usize,
+    pub old: C,
+    pub new: C,
+}
+
+impl<C: fmt::Display> fmt::Display for SelfModification<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "instruction at {} overwrites code at {}: {} -> {}",
+            self.pc, self.address, self.old, self.new
+        )
+    }
+}
+
+/// Keeps track of the addresses of all executed instructions (including their parameters)
+/// and of all writes to these addresses, see Machine::track_self_modifications.
+#[derive(Debug, Clone, Default)]
+pub struct SelfModifications<C = Opcode> {
+    // indexed by address, whether it belongs to an executed instruction
+    code: Vec<bool>,
+    modifications: Vec<SelfModification<C>>,
+    warn: bool,
+}
+
+impl<C: Clone + PartialEq + fmt::Display> SelfModifications<C> {
+    pub(crate) fn new(warn: bool) -> Self {
+        Self {
+            code: Vec::new(),
+            modifications: Vec::new(),
+            warn,
+        }
+    }
+
+    /// Marks the instruction at pc with the given length as code.
+    pub(crate) fn executed(&mut self, pc: usize, len: usize) {
+        if pc + len > self.code.len() {
+            self.code.resize(pc + len, false);
+        }
+        for is_code in self.code[pc..pc + len].iter_mut() {
+            *is_code = true;
+        }
+    }
+
+    /// Records the write of the instruction at pc, if it changes code.
+    pub(crate) fn write(&mut self, pc: usize, address: usize, old: &C, new: &C) {
+        if !self.is_code(address) || old == new {
+            return;
+        }
+        let modification = SelfModification {
+            pc,
+            address,
+            old: old.clone(),
+            new: new.clone(),
+        };
+        if self.warn {
+            eprintln!("warning: {}", modification);
+        }
+        self.modifications.push(modification);
+    }
+
+    /// Whether the address belongs to an instruction that was executed.
+    pub fn is_code(&self, address: usize) -> bool {
+        self.code.get(address).cloned().unwrap_or(false)
+    }
+
+    /// All recorded writes to code, in the order they happened.
+    pub fn modifications(&self) -> &[SelfModification<C>] {
+        &self.modifications
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modifications.is_empty()
+    }
+
+    /// All addresses of code that was overwritten.
+    pub fn addresses(&self) -> BTreeSet<usize> {
+        self.modifications.iter().map(|m| m.address).collect()
+    }
+
+    /// The number of writes to code by every writing instruction.
+    pub fn writers(&self) -> BTreeMap<usize, usize> {
+        let mut writers = BTreeMap::new();
+        for modification in self.modifications.iter() {
+            *writers.entry(modification.pc).or_default() += 1;
+        }
+        writers
+    }
+}
+
+/// A summary with one line per writing instruction, for example
+/// `instruction at 873 overwrites code at 878 (12 times)`.
+impl<C: Clone + PartialEq + fmt::Display> fmt::Display for SelfModifications<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} writes to code at {} addresses",
+            self.modifications.len(),
+            self.addresses().len()
+        )?;
+        for (pc, count) in self.writers() {
+            let addresses: BTreeSet<usize> = self
+                .modifications
+                .iter()
+                .filter(|m| m.pc == pc)
+                .map(|m| m.address)
+                .collect();
+            let addresses: Vec<String> = addresses.iter().map(|adr| adr.to_string()).collect();
+            writeln!(
+                f,
+                "instruction at {} overwrites code at {} ({} times)",
+                pc,
+                addresses.join(","),
+                count
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{assemble, Machine, VecIo};
+
+    #[test]
+    fn test_self_modifications() {
+        let program =
+            assemble("OUT #1\nADD #5, #0 -> [1]\nADD #5, #0 -> [1]\nADD #7, #0 -> [18]\nHLT")
+                .unwrap();
+        let mut machine = Machine::new(program);
+        machine.track_self_modifications(false);
+        machine.run(VecIo::default()).unwrap();
+
+        // The second write does not change anything and the write to 18 does not hit code.
+        let tracked = machine.self_modifications().unwrap();
+        assert!(tracked.is_code(1));
+        assert!(!tracked.is_code(18));
+        assert_eq!(tracked.modifications().len(), 1);
+        assert_eq!(
+            tracked.modifications()[0].to_string(),
+            "instruction at 2 overwrites code at 1: 1 -> 5"
+        );
+        assert_eq!(
+            tracked.to_string(),
+            "1 writes to code at 1 addresses\ninstruction at 2 overwrites code at 1 (1 times)\n"
+        );
+    }
+}
@@ -0,0 +1,321 @@
+//! Recording the IO of a program to a session file and replaying it later on without the original IO.
+//!
+//! A session file starts with a header line, followed by one line per IO operation:
+//! the number of instructions executed before it, `<` for inputs or `>` for outputs and the value.
+//!
+//! ```text
+//! intcode session 1
+//! 0 < 1
+//! 27 > 0
+//! 31 > 1
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+
+use crate::{IntcodeError, IntcodeIo, IoEvent, Machine, Memory, Opcode};
+
+/// First line of every session file, including the version of the format.
+const SESSION_HEADER: &str = "intcode session 1";
+
+/// A single IO operation of a session, with the number of instructions the machine executed before it.
+/// Outputs are recorded after the output instruction, inputs before the input instruction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SessionEvent {
+    Input { executed: u64, value: Opcode },
+    Output { executed: u64, value: Opcode },
+}
+
+impl fmt::Display for SessionEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionEvent::Input { executed, value } => write!(f, "{} < {}", executed, value),
+            SessionEvent::Output { executed, value } => write!(f, "{} > {}", executed, value),
+        }
+    }
+}
+
+impl SessionEvent {
+    fn parse(line: &str) -> Option<SessionEvent> {
+        let mut words = line.split_whitespace();
+        let executed = words.next()?.parse().ok()?;
+        let direction = words.next()?;
+        let value = words.next()?.parse().ok()?;
+        if words.next().is_some() {
+            return None;
+        }
+        match direction {
+            "<" => Some(SessionEvent::Input { executed, value }),
+            ">" => Some(SessionEvent::Output { executed, value }),
+            _ => None,
+        }
+    }
+}
+
+/// The error for an IO failure of the instruction at pc.
+/// IO instructions do not change the memory, so the opcode is still there after executing it.
+/// It is only looked up once something failed, to keep the run loops cheap.
+fn io_error<M: Memory<Cell = Opcode>>(
+    machine: &Machine<M>,
+    pc: usize,
+    message: String,
+) -> IntcodeError {
+    IntcodeError::Io {
+        pc,
+        opcode: machine.memory()[pc],
+        message,
+    }
+}
+
+/// Runs a Machine with the IO of another IntcodeIo and logs every read and write to a session.
+///
+/// This is not an IntcodeIo wrapping another one, because every event is logged with the number of
+/// instructions executed before it, which is only known to the Machine. Replaying checks these numbers,
+/// so a session also catches programs, that do the same IO at different points of time.
+pub struct Recorder<T: IntcodeIo, W: Write> {
+    inner: T,
+    session: W,
+    header_written: bool,
+}
+
+impl<T: IntcodeIo, W: Write> Recorder<T, W> {
+    pub fn new(inner: T, session: W) -> Self {
+        Self {
+            inner,
+            session,
+            header_written: false,
+        }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> (T, W) {
+        (self.inner, self.session)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if !self.header_written {
+            writeln!(self.session, "{}", SESSION_HEADER)?;
+            self.header_written = true;
+        }
+        Ok(())
+    }
+
+    fn log(&mut self, event: SessionEvent) -> io::Result<()> {
+        writeln!(self.session, "{}", event)
+    }
+
+    /// Run the machine until it halts, like Machine::run with the inner IO.
+    pub fn run<M: Memory<Cell = Opcode>>(
+        &mut self,
+        machine: &mut Machine<M>,
+    ) -> Result<(), IntcodeError> {
+        // even a program without any IO gets a valid session
+        self.write_header()
+            .map_err(|err| io_error(machine, machine.pc(), err.to_string()))?;
+        loop {
+            let pc = machine.pc();
+            let event = match machine.step()? {
+                None => continue,
+                Some(IoEvent::NeedsInput) => {
+                    let value = self
+                        .inner
+                        .try_read()
+                        .map_err(|message| io_error(machine, pc, message))?;
+                    machine.push_input(value);
+                    SessionEvent::Input {
+                        executed: machine.executed_instructions(),
+                        value,
+                    }
+                }
+                Some(IoEvent::Output(value)) => {
+                    self.inner
+                        .try_write(&value)
+                        .map_err(|message| io_error(machine, pc, message))?;
+                    SessionEvent::Output {
+                        executed: machine.executed_instructions(),
+                        value,
+                    }
+                }
+                Some(IoEvent::Halted) => {
+                    return self
+                        .session
+                        .flush()
+                        .map_err(|err| io_error(machine, pc, err.to_string()))
+                }
+            };
+            self.log(event)
+                .map_err(|err| io_error(machine, pc, err.to_string()))?;
+        }
+    }
+}
+
+/// Runs a Machine with the inputs of a recorded session and fails as soon as it deviates from the session,
+/// that is if it reads or writes at another point of time or writes another value.
+#[derive(Debug, Clone, Default)]
+pub struct Replayer {
+    events: VecDeque<SessionEvent>,
+}
+
+impl Replayer {
+    pub fn new<I: IntoIterator<Item = SessionEvent>>(events: I) -> Self {
+        Self {
+            events: events.into_iter().collect(),
+        }
+    }
+
+    /// Read a session in the format written by Recorder.
+    pub fn load<R: BufRead>(reader: R) -> io::Result<Self> {
+        let invalid_data = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut lines = reader.lines();
+        match lines.next() {
+            Some(Ok(ref header)) if header == SESSION_HEADER => (),
+            Some(Err(err)) => return Err(err),
+            _ => return Err(invalid_data(String::from("missing session header"))),
+        }
+
+        let mut events = VecDeque::new();
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event = SessionEvent::parse(&line)
+                .ok_or_else(|| invalid_data(format!("line {}: invalid event '{}'", i + 2, line)))?;
+            events.push_back(event);
+        }
+        Ok(Self { events })
+    }
+
+    pub fn load_from_file(filename: &str) -> io::Result<Self> {
+        Self::load(BufReader::new(File::open(filename)?))
+    }
+
+    /// Number of events that were not replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Replay the session until the machine halts, which is expected to happen after the last event.
+    pub fn run<M: Memory<Cell = Opcode>>(
+        &mut self,
+        machine: &mut Machine<M>,
+    ) -> Result<(), IntcodeError> {
+        loop {
+            let pc = machine.pc();
+            let executed = machine.executed_instructions();
+            let expected = self.events.front().cloned();
+            let mismatch = |machine: &Machine<M>, actual: String| {
+                let expected = expected.map_or(String::from("end of session"), |e| e.to_string());
+                io_error(
+                    machine,
+                    pc,
+                    format!("expected '{}', but got '{}'", expected, actual),
+                )
+            };
+
+            match machine.step()? {
+                None => (),
+                Some(IoEvent::NeedsInput) => match expected {
+                    Some(SessionEvent::Input {
+                        executed: expected_executed,
+                        value,
+                    }) if expected_executed == executed => {
+                        self.events.pop_front();
+                        machine.push_input(value);
+                    }
+                    _ => return Err(mismatch(machine, format!("{} < ?", executed))),
+                },
+                Some(IoEvent::Output(value)) => {
+                    let actual = SessionEvent::Output {
+                        executed: machine.executed_instructions(),
+                        value,
+                    };
+                    if expected != Some(actual) {
+                        return Err(mismatch(machine, actual.to_string()));
+                    }
+                    self.events.pop_front();
+                }
+                Some(IoEvent::Halted) if expected.is_none() => return Ok(()),
+                Some(IoEvent::Halted) => return Err(mismatch(machine, String::from("halt"))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assemble, VecIo};
+    use std::cell::RefCell;
+
+    const ACCUMULATOR: &str = "
+loop: IN -> [value]
+      JZ [value], #end
+      ADD [sum], [value] -> [sum]
+      OUT [sum]
+      JZ #0, #loop
+end:  HLT
+sum:  DATA 0
+value: DATA 0
+";
+
+    fn record(source: &str, input: Vec<Opcode>) -> String {
+        let mut machine = Machine::new(assemble(source).unwrap());
+        let mut recorder = Recorder::new(RefCell::new(VecIo::new(input)), Vec::new());
+        recorder.run(&mut machine).unwrap();
+        String::from_utf8(recorder.into_inner().1).unwrap()
+    }
+
+    #[test]
+    fn test_record() {
+        assert_eq!(
+            record(ACCUMULATOR, vec![3, 4, 0]),
+            "intcode session 1
+0 < 3
+4 > 3
+5 < 4
+9 > 7
+10 < 0
+"
+        );
+    }
+
+    #[test]
+    fn test_record_without_io() {
+        let session = record("ADD #1, #2 -> [5]\nHLT", vec![]);
+        assert_eq!(session, "intcode session 1\n");
+
+        let mut replayer = Replayer::load(session.as_bytes()).unwrap();
+        assert_eq!(replayer.remaining(), 0);
+        let mut machine = Machine::new(assemble("ADD #1, #2 -> [5]\nHLT").unwrap());
+        assert_eq!(replayer.run(&mut machine), Ok(()));
+    }
+
+    #[test]
+    fn test_replay() {
+        let session = record(ACCUMULATOR, vec![3, 4, 0]);
+        let mut replayer = Replayer::load(session.as_bytes()).unwrap();
+        let mut machine = Machine::new(assemble(ACCUMULATOR).unwrap());
+        assert_eq!(replayer.run(&mut machine), Ok(()));
+        assert_eq!(replayer.remaining(), 0);
+
+        // Doubling the sum changes the second output.
+        let changed = ACCUMULATOR.replace("OUT [sum]", "MUL [sum], #2 -> [sum]\nOUT [sum]");
+        let mut replayer = Replayer::load(session.as_bytes()).unwrap();
+        let mut machine = Machine::new(assemble(&changed).unwrap());
+        match replayer.run(&mut machine) {
+            Err(IntcodeError::Io { message, .. }) => {
+                assert_eq!(message, "expected '4 > 3', but got '5 > 6'")
+            }
+            other => panic!("replay did not fail: {:?}", other),
+        }
+
+        assert!(Replayer::load("0 < 1\n".as_bytes()).is_err());
+        assert!(Replayer::load("intcode session 1\n0 < x\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,156 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+
+use crate::{Memory, Opcode, ProgramMemory};
+
+/// First line of every saved snapshot, including the version of the format.
+const SNAPSHOT_HEADER: &str = "intcode snapshot 1";
+
+/// The complete state of a Machine, that can be restored later on.
+/// Only snapshots with the dense ProgramMemory can be saved, since the text format lists every value.
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot<M: Memory = ProgramMemory> {
+    pub memory: M,
+    pub pc: usize,
+    pub relative_base: isize,
+    /// Input that was queued, but not yet read by the program.
+    pub input: Vec<M::Cell>,
+    pub halted: bool,
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn join(values: impl Iterator<Item = Opcode>) -> String {
+    values.map(|v| v.to_string()).collect::<Vec<_>>().join(",")
+}
+
+impl Snapshot {
+    /// Write the snapshot in a simple line based text format:
+    /// a header line followed by one `key value` line for every field, lists are comma separated.
+    pub fn save<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        // Memory is zero beyond its length anyways, so trailing zeros do not need to be saved.
+        let memory_len = self
+            .memory
+            .iter()
+            .rposition(|&value| value != 0)
+            .map_or(0, |i| i + 1);
+
+        writeln!(writer, "{}", SNAPSHOT_HEADER)?;
+        writeln!(writer, "pc {}", self.pc)?;
+        writeln!(writer, "relative_base {}", self.relative_base)?;
+        writeln!(writer, "halted {}", self.halted)?;
+        writeln!(writer, "input {}", join(self.input.iter().cloned()))?;
+        writeln!(
+            writer,
+            "memory {}",
+            join(self.memory.iter().take(memory_len).cloned())
+        )?;
+        writer.flush()
+    }
+
+    /// Read a snapshot in the format written by save.
+    pub fn load<R: BufRead>(reader: R) -> io::Result<Snapshot> {
+        let mut lines = reader.lines();
+        match lines.next() {
+            Some(Ok(ref header)) if header == SNAPSHOT_HEADER => (),
+            Some(Err(err)) => return Err(err),
+            _ => return Err(invalid_data(String::from("missing snapshot header"))),
+        }
+
+        let mut snapshot = Snapshot::default();
+        for line in lines {
+            let line = line?;
+            let (key, value) = match line.find(' ') {
+                Some(i) => (&line[..i], &line[i + 1..]),
+                None => (line.as_str(), ""),
+            };
+            let invalid = || invalid_data(format!("invalid value for {}: '{}'", key, value));
+            let list = || -> io::Result<Vec<Opcode>> {
+                value
+                    .split(',')
+                    .filter(|v| !v.is_empty())
+                    .map(|v| v.parse().map_err(|_| invalid()))
+                    .collect()
+            };
+
+            match key {
+                "pc" => snapshot.pc = value.parse().map_err(|_| invalid())?,
+                "relative_base" => snapshot.relative_base = value.parse().map_err(|_| invalid())?,
+                "halted" => snapshot.halted = value.parse().map_err(|_| invalid())?,
+                "input" => snapshot.input = list()?,
+                "memory" => snapshot.memory = list()?.into_iter().collect(),
+                "" => (),
+                _ => return Err(invalid_data(format!("unknown key '{}'", key))),
+            }
+        }
+
+        Ok(snapshot)
+    }
+
+    pub fn save_to_file(&self, filename: &str) -> io::Result<()> {
+        self.save(BufWriter::new(File::create(filename)?))
+    }
+
+    pub fn load_from_file(filename: &str) -> io::Result<Snapshot> {
+        Self::load(BufReader::new(File::open(filename)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assemble, IoEvent, Machine};
+
+    const ACCUMULATOR: &str = "
+loop: IN -> [value]
+      ADD [sum], [value] -> [sum]
+      OUT [sum]
+      JZ #0, #loop
+sum:  DATA 0
+value: DATA 0
+";
+
+    #[test]
+    fn test_restore() {
+        let mut machine = Machine::new(assemble(ACCUMULATOR).unwrap());
+        machine.push_input(3);
+        assert_eq!(machine.run_until_io(), Ok(IoEvent::Output(3)));
+
+        // Queued input is part of the snapshot as well.
+        machine.push_input(4);
+        let snapshot = machine.snapshot();
+
+        assert_eq!(machine.run_until_io(), Ok(IoEvent::Output(7)));
+        machine.push_input(10);
+        assert_eq!(machine.run_until_io(), Ok(IoEvent::Output(17)));
+
+        machine.restore(&snapshot);
+        assert_eq!(machine.run_until_io(), Ok(IoEvent::Output(7)));
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut machine = Machine::new(assemble(ACCUMULATOR).unwrap());
+        machine.push_input(3);
+        machine.run_until_io().unwrap();
+        machine.push_input(-4);
+
+        let mut saved = Vec::new();
+        machine.snapshot().save(&mut saved).unwrap();
+        let loaded = Snapshot::load(saved.as_slice()).unwrap();
+
+        assert_eq!(loaded.pc, machine.pc());
+        assert_eq!(loaded.input, vec![-4]);
+        let mut restored = Machine::from_snapshot(loaded);
+        assert_eq!(restored.run_until_io(), Ok(IoEvent::Output(-1)));
+    }
+
+    #[test]
+    fn test_load_errors() {
+        assert!(Snapshot::load("pc 0\n".as_bytes()).is_err());
+        assert!(Snapshot::load("intcode snapshot 1\npc -1\n".as_bytes()).is_err());
+        assert!(Snapshot::load("intcode snapshot 1\nmemory 1,x\n".as_bytes()).is_err());
+    }
+}
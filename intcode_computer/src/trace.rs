@@ -0,0 +1,179 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::thread;
+
+use crate::{Instruction, Opcode};
+
+/// Everything that happened while executing a single instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceRecord<C = Opcode> {
+    pub pc: usize,
+    pub instruction: Instruction,
+    /// The relative base before executing the instruction.
+    pub relative_base: isize,
+    /// The resolved addresses of all parameters.
+    pub addresses: Vec<usize>,
+    /// The values of all parameters, that are read (that is all but the one written to).
+    pub reads: Vec<C>,
+    /// The address and value written by the instruction.
+    pub write: Option<(usize, C)>,
+}
+
+/// Formats a list of values without spaces, so that every field of a trace line is a single word.
+fn join<T: ToString>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// A single line, as written by LineTracer, for example
+/// `12 21201 ADD rb=1000 adr=1003,15,100 read=7,5 write=100:12`.
+impl<C: fmt::Display> fmt::Display for TraceRecord<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} rb={} adr={} read={} write=",
+            self.pc,
+            self.instruction.opcode,
+            self.instruction.mnemonic(),
+            self.relative_base,
+            join(&self.addresses),
+            join(&self.reads)
+        )?;
+        match &self.write {
+            Some((adr, value)) => write!(f, "{}:{}", adr, value),
+            None => write!(f, "-"),
+        }
+    }
+}
+
+/// Receives a record of every instruction executed by Machine::step_traced and Machine::run_traced.
+pub trait Tracer<C = Opcode> {
+    fn trace(&mut self, record: &TraceRecord<C>);
+}
+
+/// Writes every record as a line of text.
+pub struct LineTracer<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> LineTracer<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl LineTracer<BufWriter<File>> {
+    /// Creates a tracer writing to the file at path, which is truncated if it already exists.
+    pub fn create(path: &str) -> io::Result<Self> {
+        File::create(path).map(|file| Self::new(BufWriter::new(file)))
+    }
+}
+
+impl<C: fmt::Display, W: Write> Tracer<C> for LineTracer<W> {
+    fn trace(&mut self, record: &TraceRecord<C>) {
+        writeln!(self.writer, "{}", record).expect("Could not write the trace");
+    }
+}
+
+/// Keeps the records of the last few instructions.
+/// If this tracer is dropped while the thread is panicking, the records are dumped to stderr.
+pub struct RingBufferTracer {
+    records: VecDeque<TraceRecord>,
+    capacity: usize,
+}
+
+impl RingBufferTracer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// The kept records, oldest first.
+    pub fn records(&self) -> impl Iterator<Item = &TraceRecord> {
+        self.records.iter()
+    }
+
+    /// Write the kept records, oldest first.
+    pub fn dump<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for record in self.records.iter() {
+            writeln!(writer, "{}", record)?;
+        }
+        Ok(())
+    }
+}
+
+impl Tracer for RingBufferTracer {
+    fn trace(&mut self, record: &TraceRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(record.clone());
+    }
+}
+
+impl Drop for RingBufferTracer {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            eprintln!("Last {} executed instructions:", self.records.len());
+            // There is nothing left to do, if even stderr fails.
+            self.dump(io::stderr()).ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assemble, Machine};
+
+    #[test]
+    fn test_line_tracer() {
+        let program =
+            assemble("ARB #7\nADD [r+4], #-3 -> [r+5]\nIN -> [12]\nOUT [12]\nHLT\nDATA 2").unwrap();
+        let mut machine = Machine::new(program);
+        machine.push_input(5);
+
+        let mut tracer = LineTracer::new(Vec::new());
+        while !machine.is_halted() {
+            machine.step_traced(&mut tracer).unwrap();
+        }
+
+        assert_eq!(
+            String::from_utf8(tracer.into_inner()).unwrap(),
+            "0 109 ARB rb=0 adr=1 read=7 write=-
+2 21201 ADD rb=7 adr=11,4,12 read=2,-3 write=12:-1
+6 3 IN rb=7 adr=12 read= write=12:5
+8 4 OUT rb=7 adr=12 read=5 write=-
+10 99 HLT rb=7 adr= read= write=-
+"
+        );
+    }
+
+    #[test]
+    fn test_ring_buffer_tracer() {
+        let program = assemble("loop: JNZ #1, #loop").unwrap();
+        let mut machine = Machine::new(program);
+
+        let mut tracer = RingBufferTracer::new(3);
+        for _ in 0..10 {
+            machine.step_traced(&mut tracer).unwrap();
+        }
+
+        assert_eq!(tracer.records().count(), 3);
+        assert!(tracer.records().all(|record| record.pc == 0));
+    }
+}
@@ -1,35 +1,40 @@
 mod types;
 
 use crate::types::{Axis, BlockType, Coordinate, Direction};
-use intcode_channel_io::IntcodeThread;
-use intcode_computer::ProgramMemory;
+use intcode_computer::{IoEvent, Machine, ProgramMemory};
 use std::collections::{HashMap, VecDeque};
 
 pub struct RepairRobotControl {
     map: HashMap<Coordinate, BlockType>,
-    thread: IntcodeThread,
+    machine: Machine,
 }
 
 impl RepairRobotControl {
     pub fn new(program: ProgramMemory) -> Self {
         let map = HashMap::new();
+        let machine = Machine::new(program);
 
-        let identifier = String::from("Robot");
-        let mut thread = IntcodeThread::new(program, Some(identifier));
-        thread.hide_debug_messages = true;
-
-        Self { map, thread }
+        Self { map, machine }
     }
 
     fn move_robot_towards(&mut self, dir: Direction) -> BlockType {
         // Task protocol dictates to first send a direction and then receive the block the robot stands on.
-        self.thread.send(dir.into());
-        self.thread.recv().unwrap().into()
+        self.machine.push_input(dir.into());
+        match self.machine.run_until_io() {
+            Ok(IoEvent::Output(block)) => block.into(),
+            other => panic!(
+                "Robot did not report the block it moved towards: {:?}",
+                other
+            ),
+        }
     }
 
     fn reveal_map_from(&mut self, pos: Coordinate) {
         use Direction::*;
 
+        // The state of the robot standing on pos, every direction is explored starting from here.
+        let state = self.machine.snapshot();
+
         for &dir in [North, West, South, East].iter() {
             let pos = pos + dir;
 
@@ -47,10 +52,11 @@ impl RepairRobotControl {
                 // The search is recursively continued from the current field.
                 self.reveal_map_from(pos);
 
-                // Once this is done, we move the robot back to where it came from.
+                // Once this is done, the robot is put back to where it came from by restoring the saved state,
+                // instead of walking it back.
                 // Similarly, if the field was wall, the robot would not have moved in it,
-                // so it does not need to be moved back.
-                self.move_robot_towards(dir.inverse());
+                // so it does not need to be put back.
+                self.machine.restore(&state);
             }
         }
     }